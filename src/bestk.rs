@@ -0,0 +1,79 @@
+use crate::rounds::BESTK_NONE;
+use crate::{mask_to_u64, DpowNanoMsgHdr};
+
+// iguana's bestk and bestmask selection from dpow_fsm.c
+//
+// every notary takes the notaries it has received from (its recvmask) and, starting at an
+// offset that rotates with the height, adds them to the mask until it has minsigs of them.
+// bestk is the last one added and bestmask the notaries that will sign. the pending crc
+// check in dpow_maskmin is left out, as the crcs of other notaries aren't in a message
+
+// DPOW_CHECKPOINTFREQ, how many blocks the starting offset stays the same for
+pub const DPOW_CHECKPOINTFREQ: u32 = 10;
+
+// DPOW_MODIND, the notary `offset` places after the one the height starts at
+pub fn dpow_modind(height: u32, numnotaries: usize, offset: usize) -> usize {
+    ((height / DPOW_CHECKPOINTFREQ) as usize % numnotaries + offset) % numnotaries
+}
+
+// dpow_maskmin: the first minsigs notaries of refmask counting from the height's offset,
+// and the last of them; require0 makes notary 0 a signer whether or not it is in refmask
+pub fn dpow_maskmin(
+    refmask: u64,
+    height: u32,
+    numnotaries: usize,
+    minsigs: usize,
+    require0: bool,
+) -> Option<(u8, u64)> {
+    let mut mask = require0 as u64;
+    let mut m = 0;
+    for j in 0..numnotaries {
+        let k = dpow_modind(height, numnotaries, j);
+        if (!require0 || k != 0) && refmask & (1 << k) != 0 {
+            mask |= 1 << k;
+            m += 1;
+            if m == minsigs - require0 as usize {
+                return Some((k as u8, mask));
+            }
+        }
+    }
+    None
+}
+
+// dpow_bestk: (bestk, bestmask) a notary with this recvmask should report, or
+// (BESTK_NONE, 0) if it hasn't received from enough notaries yet
+pub fn dpow_bestk(recvmask: u64, height: u32, numnotaries: usize, minsigs: usize) -> (u8, u64) {
+    dpow_maskmin(recvmask, height, numnotaries, minsigs, false).unwrap_or((BESTK_NONE, 0))
+}
+
+// what a notary reported that disagrees with its own recvmask and the consensus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BestkMismatch {
+    pub reported: (u8, u64),
+    pub expected: (u8, u64),
+}
+
+// checks the notarize bestk and bestmask of a message against its recvmask. once most
+// notaries agree on a bestmask, dpow_bestconsensus has a notary report that instead of its
+// own, so `adopted` is also accepted: the pair the other notaries of the round report most
+pub fn check_bestk(
+    dpow_msg: &DpowNanoMsgHdr,
+    numnotaries: usize,
+    minsigs: usize,
+    adopted: Option<(u8, u64)>,
+) -> Result<(), BestkMismatch> {
+    let notarize = &dpow_msg.notarize;
+    let reported = (notarize.bestk, mask_to_u64(&notarize.bestmask));
+    let expected = dpow_bestk(
+        mask_to_u64(&notarize.recvmask),
+        dpow_msg.height,
+        // a mask has no bits for any more
+        numnotaries.min(64),
+        minsigs,
+    );
+    if reported == expected || Some(reported) == adopted {
+        Ok(())
+    } else {
+        Err(BestkMismatch { reported, expected })
+    }
+}
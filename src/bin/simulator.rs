@@ -0,0 +1,50 @@
+use iguana_rs::simulator::{Fault, Simulator};
+use nanomsg::{Protocol, Socket};
+use std::env;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+// usage ./iguana_rs_simulator <url to bind to> [notaries] [interval ms] [fault every n packets]
+// binds a bus socket, eg ipc:///tmp/iguana.ipc or tcp://127.0.0.2:7775, and sends the
+// traffic of all simulated notaries on it. the listener connects to tcp://<peer>:<its own port>
+// so on tcp the simulator must use the listener's port on another loopback address, eg
+// ./iguana_rs_simulator tcp://127.0.0.2:7775 &
+// ./iguana_rs_listener 127.0.0.1 7775 127.0.0.2 sim.db
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("usage: iguana_rs_simulator <url to bind to> [notaries] [interval ms] [fault every n packets]");
+        std::process::exit(1);
+    }
+    let url = &args[1];
+    let numnotaries: usize = args.get(2).map_or(64, |n| n.parse().unwrap());
+    let interval: u64 = args.get(3).map_or(1000, |n| n.parse().unwrap());
+    // 0 disables fault injection
+    let fault_every: usize = args.get(4).map_or(0, |n| n.parse().unwrap());
+
+    let mut socket = Socket::new(Protocol::Bus).expect("cannot create socket");
+    let _endpoint = socket.bind(url).expect("cannot bind to socket");
+
+    let mut simulator = Simulator::new(numnotaries, "MARTY", 1000);
+    let mut sent = 0;
+    let mut faults = Fault::ALL.iter().cycle();
+    loop {
+        let height = simulator.height;
+        for (senderind, packet) in simulator.step().into_iter().enumerate() {
+            sent += 1;
+            let packet = if fault_every > 0 && sent % fault_every == 0 {
+                let fault = *faults.next().unwrap();
+                println!("sending {:?} packet as notary {}", fault, senderind);
+                simulator.faulty_packet(senderind as u8, fault)
+            } else {
+                packet
+            };
+            if let Err(err) = socket.write_all(&packet) {
+                println!("failed to send packet: {}", err);
+            }
+        }
+        println!("sent round step for {} notaries at height {}", numnotaries, height);
+        thread::sleep(Duration::from_millis(interval));
+    }
+}
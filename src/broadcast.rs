@@ -0,0 +1,87 @@
+use crate::config::BroadcastConfig;
+use crate::packet::encode_packet;
+use crate::simulator::blank_msg;
+use crate::transport::Transport;
+use crate::{DpowNanoMsgHdr, DPOW_SIGCHANNEL};
+use log::info;
+use secp256k1::SecretKey;
+use std::io;
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+// takes part in the bus like a notary would, periodically sending a signed message that
+// advertises our IP and the peers we are connected to
+pub struct Broadcaster {
+    sk: SecretKey,
+    senderind: u8,
+    symbol: String,
+    myipbits: [u8; 4],
+    interval: Duration,
+    next: Instant,
+}
+
+impl Broadcaster {
+    // myip is the external IP we are reachable on
+    pub fn new(config: &BroadcastConfig, myip: &str) -> Result<Self, String> {
+        let sk = hex::decode(&config.secret_key)
+            .ok()
+            .and_then(|bytes| SecretKey::from_slice(&bytes).ok())
+            .ok_or("broadcast secret_key must be a 32 byte hex encoded secp256k1 key")?;
+        if config.symbol.len() >= 16 {
+            return Err(format!("broadcast symbol {} is too long", config.symbol));
+        }
+        let myip: Ipv4Addr = myip
+            .parse()
+            .map_err(|_| format!("cannot broadcast from {}, not an IPv4 address", myip))?;
+        Ok(Broadcaster {
+            sk,
+            senderind: config.senderind,
+            symbol: config.symbol.clone(),
+            myipbits: myip.octets(),
+            interval: Duration::from_secs(config.interval_secs),
+            next: Instant::now(),
+        })
+    }
+
+    // peers are IPs we are connected to, advertised in ipbits along with our own
+    pub fn message(&self, peers: &[String]) -> DpowNanoMsgHdr {
+        let mut msg = blank_msg();
+        msg.symbol[..self.symbol.len()].copy_from_slice(self.symbol.as_bytes());
+        msg.senderind = self.senderind;
+        msg.channel = DPOW_SIGCHANNEL;
+        msg.myipbits = self.myipbits;
+
+        let ips = peers
+            .iter()
+            .filter_map(|ip| ip.parse::<Ipv4Addr>().ok())
+            .map(|ip| ip.octets())
+            .filter(|ipbits| *ipbits != self.myipbits);
+        msg.ipbits[0] = self.myipbits;
+        let mut numipbits = 1;
+        for ipbits in ips.take(msg.ipbits.len() - 1) {
+            msg.ipbits[numipbits] = ipbits;
+            numipbits += 1;
+        }
+        msg.numipbits = numipbits as u32;
+        msg
+    }
+
+    pub fn packet(&self, peers: &[String]) -> Vec<u8> {
+        encode_packet(&self.message(peers), &[], &self.sk).unwrap()
+    }
+
+    pub fn is_due(&self) -> bool {
+        Instant::now() >= self.next
+    }
+
+    // sends our message if the interval has passed since the last one
+    pub fn poll(&mut self, transport: &mut dyn Transport, peers: &[String]) -> io::Result<bool> {
+        if !self.is_due() {
+            return Ok(false);
+        }
+        self.next = Instant::now() + self.interval;
+        transport.send(&self.packet(peers))?;
+        info!(senderind = self.senderind, peers = peers.len(); "broadcast our message");
+        Ok(true)
+    }
+}
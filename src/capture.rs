@@ -0,0 +1,195 @@
+use crate::config::CaptureConfig;
+use crate::sp::DEFAULT_RECEIVE_MAX_SIZE;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// capture file format, all integers little endian:
+//   magic      8 bytes  "IGNCAP01"
+// followed by one record per nanomsg message read from the bus socket:
+//   received   u64      receive time in microseconds since the unix epoch
+//   len        u32      length of data
+//   data       len bytes the raw message, possibly several iguana packets
+// a record is written with a single write and flushed, so a crash can at most
+// leave a truncated final record, which the reader reports as an error
+pub const CAPTURE_MAGIC: [u8; 8] = *b"IGNCAP01";
+
+pub fn now_micros() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_micros() as u64
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedMessage {
+    pub received: u64,
+    pub data: Vec<u8>,
+}
+
+// appends to config.path; when the file grows past max_bytes or is older than
+// max_age_secs it is renamed to <path>.<first record time in seconds> and a new one started
+pub struct CaptureWriter {
+    config: CaptureConfig,
+    file: BufWriter<File>,
+    size: u64,
+    // receive time of the first record in the current file
+    started: Option<u64>,
+}
+
+impl CaptureWriter {
+    pub fn new(config: &CaptureConfig) -> io::Result<Self> {
+        let (file, size) = open_capture(&config.path)?;
+        Ok(CaptureWriter {
+            config: config.clone(),
+            file,
+            size,
+            // an existing file is rotated on age from the time we reopened it
+            started: None,
+        })
+    }
+
+    pub fn write(&mut self, received: u64, data: &[u8]) -> io::Result<()> {
+        if self.should_rotate(received) {
+            self.rotate()?;
+        }
+
+        let mut record = Vec::with_capacity(12 + data.len());
+        record.write_u64::<LittleEndian>(received)?;
+        record.write_u32::<LittleEndian>(data.len() as u32)?;
+        record.extend_from_slice(data);
+        self.file.write_all(&record)?;
+        self.file.flush()?;
+
+        self.size += record.len() as u64;
+        self.started.get_or_insert(received);
+        Ok(())
+    }
+
+    fn should_rotate(&self, received: u64) -> bool {
+        let too_big = self
+            .config
+            .max_bytes
+            .is_some_and(|max_bytes| self.size >= max_bytes);
+        let too_old = match (self.config.max_age_secs, self.started) {
+            (Some(max_age), Some(started)) => {
+                received.saturating_sub(started) >= max_age * 1_000_000
+            }
+            _ => false,
+        };
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let started = self.started.unwrap_or_else(now_micros) / 1_000_000;
+        let mut rotated = format!("{}.{}", self.config.path, started);
+        // several rotations within a second must not overwrite each other
+        let mut n = 1;
+        while fs::metadata(&rotated).is_ok() {
+            rotated = format!("{}.{}.{}", self.config.path, started, n);
+            n += 1;
+        }
+        fs::rename(&self.config.path, &rotated)?;
+
+        let (file, size) = open_capture(&self.config.path)?;
+        self.file = file;
+        self.size = size;
+        self.started = None;
+        Ok(())
+    }
+}
+
+fn open_capture(path: &str) -> io::Result<(BufWriter<File>, u64)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut size = file.metadata()?.len();
+    let mut file = BufWriter::new(file);
+    if size == 0 {
+        file.write_all(&CAPTURE_MAGIC)?;
+        file.flush()?;
+        size = CAPTURE_MAGIC.len() as u64;
+    }
+    Ok((file, size))
+}
+
+// iterates over the records of a capture file, ending after the first error as the
+// position in the file is unknown from then on
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    // records longer than this are reported as corrupt rather than allocated
+    max_len: usize,
+    failed: bool,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: &str) -> io::Result<Self> {
+        CaptureReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != CAPTURE_MAGIC {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "not an iguana capture file",
+            ));
+        }
+        Ok(CaptureReader {
+            reader,
+            max_len: DEFAULT_RECEIVE_MAX_SIZE,
+            failed: false,
+        })
+    }
+
+    // for captures taken with a larger receive_max_size
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        // a clean end of file is only allowed between records
+        let mut first = [0u8; 1];
+        match self.reader.read(&mut first) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(err) => {
+                self.failed = true;
+                return Some(Err(err));
+            }
+        }
+        let max_len = self.max_len;
+        let mut read_record = || {
+            let mut rest = [0u8; 7];
+            self.reader.read_exact(&mut rest)?;
+            let mut received = [0u8; 8];
+            received[0] = first[0];
+            received[1..].copy_from_slice(&rest);
+            let received = u64::from_le_bytes(received);
+            let len = self.reader.read_u32::<LittleEndian>()?;
+            if len as usize > max_len {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("capture record of {} bytes is over {} bytes", len, max_len),
+                ));
+            }
+            let mut data = vec![0u8; len as usize];
+            self.reader.read_exact(&mut data)?;
+            Ok(CapturedMessage { received, data })
+        };
+        let record = read_record();
+        self.failed = record.is_err();
+        Some(record)
+    }
+}
@@ -0,0 +1,283 @@
+use crate::dedup::{DEFAULT_SEEN_CAPACITY, DEFAULT_SEEN_MAX_AGE};
+use crate::packet::DecodeLimits;
+use crate::sp::DEFAULT_RECEIVE_MAX_SIZE;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+// optional JSON config file passed as the last argument to iguana_rs_listener
+// every field has a default, so an empty object is a valid config, eg:
+// {
+//     "transport": "native",
+//     "rpc": {
+//         "bind": "0.0.0.0:3030",
+//         "read_auth": { "basic": { "username": "dashboard", "password": "hunter2" } },
+//         "admin_auth": { "bearer": "0123456789abcdef" },
+//         "cors": ["https://dashboard.example.com"],
+//         "allowed_hosts": ["listener.internal:3030"]
+//     },
+//     "ws": {
+//         "bind": "127.0.0.1:3031",
+//         "allowed_origins": ["https://dashboard.example.com"]
+//     },
+//     "metrics": { "bind": "127.0.0.1:9100" },
+//     "capture": { "path": "iguana.cap", "max_bytes": 104857600, "max_age_secs": 86400 },
+//     "broadcast": { "secret_key": "<64 hex chars>", "senderind": 8, "interval_secs": 60 },
+//     "dedup": { "capacity": 65536, "max_age_secs": 600 },
+//     "rate_limit": { "window_secs": 60, "max_per_sender": 600, "max_per_pubkey": 600, "drop": true },
+//     "limits": { "max_message_size": 1048576, "max_packet_size": 131072, "max_datalen": 65536 },
+//     "notaries": [{ "name": "blackice_DEV", "pubkey": "<66 hex chars>" }],
+//     "logging": {
+//         "level": "info",
+//         "filters": { "iguana_rs::message": "warn", "iguana_rs::peers": "debug" },
+//         "format": "json"
+//     }
+// }
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub transport: TransportKind,
+    pub rpc: RpcConfig,
+    // WebSocket message stream, disabled unless configured
+    pub ws: Option<WsConfig>,
+    // prometheus /metrics endpoint, disabled unless configured
+    pub metrics: Option<MetricsConfig>,
+    pub logging: LoggingConfig,
+    // raw packet capture file, disabled unless configured
+    pub capture: Option<CaptureConfig>,
+    // sign and send our own messages on the bus, disabled unless configured
+    pub broadcast: Option<BroadcastConfig>,
+    // the notaries accepted, indexed by senderind; FIRST_PARTY with any key if not configured
+    pub notaries: Option<Vec<NotaryConfig>>,
+    pub dedup: DedupConfig,
+    // spam detection, disabled unless configured
+    pub rate_limit: Option<RateLimitConfig>,
+    pub limits: LimitsConfig,
+}
+
+impl Config {
+    pub fn load(path: &str) -> Result<Config, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read config {}: {}", path, err))?;
+        let config: Config = serde_json::from_str(&contents)
+            .map_err(|err| format!("invalid config {}: {}", path, err))?;
+        // a zero window would start a new one with every message and never limit anything
+        if config
+            .rate_limit
+            .as_ref()
+            .is_some_and(|rate_limit| rate_limit.window_secs == 0)
+        {
+            return Err(format!(
+                "invalid config {}: rate_limit window_secs must be above 0",
+                path
+            ));
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    // Protocol::Bus socket from the C libnanomsg
+    #[default]
+    Nanomsg,
+    // pure Rust SP bus over TCP, see sp.rs
+    Native,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcAuth {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    pub bind: String,
+    // credentials for the read-only query methods; None leaves them open
+    pub read_auth: Option<RpcAuth>,
+    // credentials for methods that change listener state
+    // None means anyone allowed to read may also use the admin methods
+    pub admin_auth: Option<RpcAuth>,
+    // allowed CORS origins, "*" allows any; None sends no CORS headers
+    pub cors: Option<Vec<String>>,
+    // allowed Host header values in addition to the bind address; None disables the check
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            bind: "127.0.0.1:3030".to_string(),
+            read_auth: None,
+            admin_auth: None,
+            cors: None,
+            allowed_hosts: None,
+        }
+    }
+}
+
+// the message stream; connections need the rpc read_auth credentials, if set
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct WsConfig {
+    pub bind: String,
+    // allowed Origin header values; None allows any origin
+    #[serde(default)]
+    pub allowed_origins: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub bind: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // one JSON object per line
+    Json,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // off, error, warn, info, debug or trace
+    pub level: String,
+    // per-module levels keyed by module path, the longest matching prefix wins
+    pub filters: BTreeMap<String, String>,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            level: "info".to_string(),
+            filters: BTreeMap::new(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct CaptureConfig {
+    pub path: String,
+    // rotate once the file reaches this size
+    #[serde(default)]
+    pub max_bytes: Option<u64>,
+    // rotate once the first record in the file is this old
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct BroadcastConfig {
+    // hex encoded secp256k1 secret key the packets are signed with
+    pub secret_key: String,
+    // our index in the notary list, must match the key for others to accept the packets
+    pub senderind: u8,
+    #[serde(default = "default_broadcast_symbol")]
+    pub symbol: String,
+    #[serde(default = "default_broadcast_interval")]
+    pub interval_secs: u64,
+}
+
+fn default_broadcast_symbol() -> String {
+    "KMD".to_string()
+}
+
+fn default_broadcast_interval() -> u64 {
+    60
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct NotaryConfig {
+    pub name: String,
+    // hex encoded compressed pubkey; None accepts any key for this senderind
+    #[serde(default)]
+    pub pubkey: Option<String>,
+}
+
+// duplicate suppression by packethash
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DedupConfig {
+    // packethashes remembered at most
+    pub capacity: usize,
+    // how long a packethash is remembered
+    pub max_age_secs: u32,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            capacity: DEFAULT_SEEN_CAPACITY,
+            max_age_secs: DEFAULT_SEEN_MAX_AGE,
+        }
+    }
+}
+
+// per sender message rate limits, counted over fixed windows
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub window_secs: u32,
+    // messages allowed per window from one senderind
+    pub max_per_sender: u32,
+    // messages allowed per window signed by one pubkey
+    pub max_per_pubkey: u32,
+    // drop messages over the limits rather than only recording an incident
+    pub drop: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            window_secs: 60,
+            max_per_sender: 600,
+            max_per_pubkey: 600,
+            drop: false,
+        }
+    }
+}
+
+// sizes above which messages are dropped, to bound memory use on hostile input
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    // whole bus message, which may hold several packets; enforced by the socket
+    pub max_message_size: usize,
+    // one packet, header included
+    pub max_packet_size: usize,
+    // data following the DpowNanoMsgHdr in a packet
+    pub max_datalen: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        let decode = DecodeLimits::default();
+        LimitsConfig {
+            max_message_size: DEFAULT_RECEIVE_MAX_SIZE,
+            max_packet_size: decode.max_packet_size,
+            max_datalen: decode.max_datalen,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn decode_limits(&self) -> DecodeLimits {
+        DecodeLimits {
+            max_packet_size: self.max_packet_size,
+            max_datalen: self.max_datalen,
+        }
+    }
+}
@@ -1,5 +1,8 @@
-use crate::{now_sec, FIRST_PARTY};
-use rusqlite::{params, Connection};
+use crate::rounds::{Fork, RoundRecord};
+use crate::{now_sec, DPOW_MINSIGS, FIRST_PARTY};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::net::Ipv4Addr;
 
 pub fn init_db(conn: &Connection) {
@@ -28,6 +31,12 @@ pub fn init_db(conn: &Connection) {
 
     init_notaries_table(&conn, FIRST_PARTY);
     init_ip_bits_dump_table(&conn);
+    init_banned_ips_table(conn);
+    init_spam_incidents_table(conn);
+    init_rounds_tables(conn);
+    init_forks_table(conn);
+    init_notary_heights_table(conn);
+    init_notary_utxos_table(conn);
 }
 
 pub fn init_notaries_table(conn: &Connection, identities: [&str; 64]) {
@@ -80,8 +89,12 @@ fn test_init_db() {
 }
 
 pub fn update_ip_logs(conn: &Connection, notary_id: u8, ipbits: [u8; 4]) {
+    update_ip_logs_at(conn, notary_id, ipbits, now_sec());
+}
+
+// as update_ip_logs, for messages received at an earlier time, eg when replaying a capture
+pub fn update_ip_logs_at(conn: &Connection, notary_id: u8, ipbits: [u8; 4], now: u32) {
     let ip_str = Ipv4Addr::from(u32::from_be_bytes(ipbits)).to_string();
-    let now = now_sec();
 
     // Check if this server has used this IP before
     let mut stmt = conn
@@ -116,7 +129,15 @@ pub fn update_ip_logs(conn: &Connection, notary_id: u8, ipbits: [u8; 4]) {
 }
 
 pub fn update_known_ips(conn: &Connection, notary_id: u8, ips: Vec<[u8; 4]>) -> Vec<String> {
-    let current_timestamp = now_sec();
+    update_known_ips_at(conn, notary_id, ips, now_sec())
+}
+
+pub fn update_known_ips_at(
+    conn: &Connection,
+    notary_id: u8,
+    ips: Vec<[u8; 4]>,
+    current_timestamp: u32,
+) -> Vec<String> {
     let mut new_ips = vec!();
 
     for ip in ips {
@@ -125,6 +146,18 @@ pub fn update_known_ips(conn: &Connection, notary_id: u8, ips: Vec<[u8; 4]>) ->
         }
         let ip_str = Ipv4Addr::from(u32::from_be_bytes(ip)).to_string();
 
+        // banned IPs are neither dialled nor recorded, whoever reports them
+        let banned: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM banned_ips WHERE ip = ?",
+                params![ip_str],
+                |row| row.get(0),
+            )
+            .unwrap();
+        if banned > 0 {
+            continue;
+        }
+
         // Insert the IP address into the ips table if it doesn't exist already
         let rows_affected = conn.execute(
             "INSERT OR IGNORE INTO ipbits (ip) VALUES (?)",
@@ -175,9 +208,671 @@ pub fn update_known_ips(conn: &Connection, notary_id: u8, ips: Vec<[u8; 4]>) ->
 }
 
 pub fn update_lastseen(conn: &Connection, notary_id: u8) {
+    update_lastseen_at(conn, notary_id, now_sec());
+}
+
+pub fn update_lastseen_at(conn: &Connection, notary_id: u8, lastseen: u32) {
     conn.execute(
         "UPDATE notaries SET lastseen = ? WHERE id = ?",
-        params![now_sec(), notary_id],
+        params![lastseen, notary_id],
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct NotaryInfo {
+    pub id: u8,
+    pub name: String,
+    pub lastseen: u32,
+    // most recently used IP from ip_logs, None if the notary has never been seen
+    pub ip: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct IpLog {
+    pub ip: String,
+    pub first_seen: u32,
+    pub last_seen: u32,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct KnownIp {
+    pub ip: String,
+    pub first_seen: u32,
+    pub last_seen: u32,
+    // names of the notaries that have advertised this IP in their ipbits
+    pub reported_by: Vec<String>,
+}
+
+const NOTARY_INFO_QUERY: &str = "SELECT n.id, n.name, n.lastseen,
+    (SELECT ip FROM ip_logs WHERE notary_id = n.id ORDER BY last_seen DESC LIMIT 1)
+    FROM notaries n";
+
+fn notary_info_from_row(row: &Row) -> rusqlite::Result<NotaryInfo> {
+    Ok(NotaryInfo {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        lastseen: row.get(2)?,
+        ip: row.get(3)?,
+    })
+}
+
+pub fn get_notaries(conn: &Connection) -> Vec<NotaryInfo> {
+    let mut stmt = conn
+        .prepare(&format!("{} ORDER BY n.id", NOTARY_INFO_QUERY))
+        .unwrap();
+    let rows = stmt.query_map([], notary_info_from_row).unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+pub fn get_notary_by_id(conn: &Connection, notary_id: u8) -> Option<NotaryInfo> {
+    conn.query_row(
+        &format!("{} WHERE n.id = ?", NOTARY_INFO_QUERY),
+        params![notary_id],
+        notary_info_from_row,
+    )
+    .optional()
+    .unwrap()
+}
+
+pub fn get_notary_by_name(conn: &Connection, name: &str) -> Option<NotaryInfo> {
+    conn.query_row(
+        &format!("{} WHERE n.name = ?", NOTARY_INFO_QUERY),
+        params![name],
+        notary_info_from_row,
+    )
+    .optional()
+    .unwrap()
+}
+
+pub fn get_ip_history(conn: &Connection, notary_id: u8) -> Vec<IpLog> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT ip, first_seen, last_seen FROM ip_logs WHERE notary_id = ? ORDER BY first_seen",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![notary_id], |row| {
+            Ok(IpLog {
+                ip: row.get(0)?,
+                first_seen: row.get(1)?,
+                last_seen: row.get(2)?,
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+pub fn get_known_ips(conn: &Connection) -> Vec<KnownIp> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT i.ip, MIN(ni.first_seen), MAX(ni.last_seen), GROUP_CONCAT(n.name)
+            FROM ipbits i
+            JOIN notary_ipbits ni ON ni.ip_id = i.id
+            JOIN notaries n ON n.id = ni.notary_id
+            GROUP BY i.id
+            ORDER BY i.id",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map([], |row| {
+            let reported_by: String = row.get(3)?;
+            Ok(KnownIp {
+                ip: row.get(0)?,
+                first_seen: row.get(1)?,
+                last_seen: row.get(2)?,
+                reported_by: reported_by.split(',').map(String::from).collect(),
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// IPs an operator has banned via RPC; the listener won't connect to these
+pub fn init_banned_ips_table(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS banned_ips (
+        ip TEXT NOT NULL PRIMARY KEY,
+        banned_at INTEGER
+        )",
+        params![],
     )
     .unwrap();
 }
+
+pub fn ban_ip(conn: &Connection, ip: &str) {
+    conn.execute(
+        "INSERT OR REPLACE INTO banned_ips (ip, banned_at) VALUES (?, ?)",
+        params![ip, now_sec()],
+    )
+    .unwrap();
+}
+
+pub fn unban_ip(conn: &Connection, ip: &str) {
+    conn.execute("DELETE FROM banned_ips WHERE ip = ?", params![ip])
+        .unwrap();
+}
+
+pub fn get_banned_ips(conn: &Connection) -> Vec<String> {
+    let mut stmt = conn.prepare("SELECT ip FROM banned_ips ORDER BY ip").unwrap();
+    let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// senders that went over the rate limits, see ratelimit.rs
+pub fn init_spam_incidents_table(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS spam_incidents (
+        id INTEGER PRIMARY KEY,
+        senderind INTEGER,
+        pubkey TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        count INTEGER,
+        window_secs INTEGER,
+        time INTEGER
+        )",
+        params![],
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct SpamIncident {
+    pub senderind: u8,
+    // hex encoded compressed pubkey
+    pub pubkey: String,
+    // which limit was exceeded, "senderind" or "pubkey"
+    pub kind: String,
+    pub count: u32,
+    pub window_secs: u32,
+    pub time: u32,
+}
+
+pub fn insert_spam_incident(conn: &Connection, incident: &SpamIncident) {
+    conn.execute(
+        "INSERT INTO spam_incidents (senderind, pubkey, kind, count, window_secs, time)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            incident.senderind,
+            incident.pubkey,
+            incident.kind,
+            incident.count,
+            incident.window_secs,
+            incident.time
+        ],
+    )
+    .unwrap();
+}
+
+// most recent first
+pub fn get_spam_incidents(conn: &Connection, limit: u32) -> Vec<SpamIncident> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT senderind, pubkey, kind, count, window_secs, time FROM spam_incidents
+            ORDER BY time DESC, id DESC LIMIT ?",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![limit], |row| {
+            Ok(SpamIncident {
+                senderind: row.get(0)?,
+                pubkey: row.get(1)?,
+                kind: row.get(2)?,
+                count: row.get(3)?,
+                window_secs: row.get(4)?,
+                time: row.get(5)?,
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// dPoW rounds by (symbol, height) and when each notary joined them, see rounds.rs
+pub fn init_rounds_tables(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rounds (
+        symbol TEXT NOT NULL,
+        height INTEGER NOT NULL,
+        started INTEGER,
+        converged INTEGER,
+        ended INTEGER,
+        bestk INTEGER,
+        bestmask TEXT,
+        notaries INTEGER,
+        PRIMARY KEY(symbol, height)
+        )",
+        params![],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS round_notaries (
+        symbol TEXT NOT NULL,
+        height INTEGER NOT NULL,
+        notary_id INTEGER,
+        joined INTEGER,
+        PRIMARY KEY(symbol, height, notary_id),
+        FOREIGN KEY(notary_id) REFERENCES notaries(id)
+        )",
+        params![],
+    )
+    .unwrap();
+}
+
+pub fn save_round(conn: &Connection, round: &RoundRecord) {
+    conn.execute(
+        "INSERT OR REPLACE INTO rounds
+        (symbol, height, started, converged, ended, bestk, bestmask, notaries)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            round.symbol,
+            round.height,
+            round.started,
+            round.converged,
+            round.ended,
+            round.bestk,
+            round.bestmask,
+            round.notaries
+        ],
+    )
+    .unwrap();
+}
+
+pub fn save_round_notary(conn: &Connection, symbol: &str, height: u32, notary_id: u8, joined: u32) {
+    conn.execute(
+        "INSERT OR IGNORE INTO round_notaries (symbol, height, notary_id, joined)
+        VALUES (?, ?, ?, ?)",
+        params![symbol, height, notary_id, joined],
+    )
+    .unwrap();
+}
+
+// most recent first
+pub fn get_round_history(conn: &Connection, symbol: &str, limit: u32) -> Vec<RoundRecord> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT symbol, height, started, converged, ended, bestk, bestmask, notaries
+            FROM rounds WHERE symbol = ? ORDER BY height DESC LIMIT ?",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![symbol, limit], |row| {
+            Ok(RoundRecord {
+                symbol: row.get(0)?,
+                height: row.get(1)?,
+                started: row.get(2)?,
+                converged: row.get(3)?,
+                ended: row.get(4)?,
+                bestk: row.get(5)?,
+                bestmask: row.get(6)?,
+                notaries: row.get(7)?,
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// (notary_id, joined) for one round, in the order they joined
+pub fn get_round_notaries(conn: &Connection, symbol: &str, height: u32) -> Vec<(u8, u32)> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT notary_id, joined FROM round_notaries WHERE symbol = ? AND height = ?
+            ORDER BY joined, notary_id",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![symbol, height], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// rounds started within [from, to), oldest first
+pub fn get_rounds_between(conn: &Connection, from: u32, to: u32) -> Vec<RoundRecord> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT symbol, height, started, converged, ended, bestk, bestmask, notaries
+            FROM rounds WHERE started >= ? AND started < ? ORDER BY started, symbol",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok(RoundRecord {
+                symbol: row.get(0)?,
+                height: row.get(1)?,
+                started: row.get(2)?,
+                converged: row.get(3)?,
+                ended: row.get(4)?,
+                bestk: row.get(5)?,
+                bestmask: row.get(6)?,
+                notaries: row.get(7)?,
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// (symbol, height, notary_id, joined) for the rounds started within [from, to)
+pub fn get_round_notaries_between(
+    conn: &Connection,
+    from: u32,
+    to: u32,
+) -> Vec<(String, u32, u8, u32)> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT n.symbol, n.height, n.notary_id, n.joined FROM round_notaries n
+            JOIN rounds r ON r.symbol = n.symbol AND r.height = n.height
+            WHERE r.started >= ? AND r.started < ?",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![from, to], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// disagreements on srchash or desthash, sides is a JSON array of rounds::ForkSide
+pub fn init_forks_table(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS forks (
+        symbol TEXT NOT NULL,
+        height INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        detected INTEGER,
+        updated INTEGER,
+        sides TEXT NOT NULL,
+        PRIMARY KEY(symbol, height, kind)
+        )",
+        params![],
+    )
+    .unwrap();
+}
+
+// keeps the first detected time if the fork is already known
+pub fn save_fork(conn: &Connection, fork: &Fork) {
+    let sides = serde_json::to_string(&fork.sides).unwrap();
+    conn.execute(
+        "INSERT OR IGNORE INTO forks (symbol, height, kind, detected, updated, sides)
+        VALUES (?, ?, ?, ?, ?, ?)",
+        params![
+            fork.symbol,
+            fork.height,
+            fork.kind,
+            fork.detected,
+            fork.updated,
+            sides
+        ],
+    )
+    .unwrap();
+    conn.execute(
+        "UPDATE forks SET updated = ?, sides = ? WHERE symbol = ? AND height = ? AND kind = ?",
+        params![fork.updated, sides, fork.symbol, fork.height, fork.kind],
+    )
+    .unwrap();
+}
+
+// most recently updated first, optionally for one symbol
+pub fn get_forks(conn: &Connection, symbol: Option<&str>, limit: u32) -> Vec<Fork> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT symbol, height, kind, detected, updated, sides FROM forks
+            WHERE ?1 IS NULL OR symbol = ?1 ORDER BY updated DESC, height DESC LIMIT ?2",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![symbol, limit], |row| {
+            let sides: String = row.get(5)?;
+            Ok(Fork {
+                symbol: row.get(0)?,
+                height: row.get(1)?,
+                kind: row.get(2)?,
+                detected: row.get(3)?,
+                updated: row.get(4)?,
+                sides: serde_json::from_str(&sides).unwrap_or_default(),
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
+
+// the latest height and srchash each notary reported per symbol, for chain tips
+pub fn init_notary_heights_table(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notary_heights (
+        symbol TEXT NOT NULL,
+        notary_id INTEGER,
+        height INTEGER,
+        srchash TEXT,
+        updated INTEGER,
+        PRIMARY KEY(symbol, notary_id),
+        FOREIGN KEY(notary_id) REFERENCES notaries(id)
+        )",
+        params![],
+    )
+    .unwrap();
+}
+
+pub fn update_notary_height(
+    conn: &Connection,
+    symbol: &str,
+    notary_id: u8,
+    height: u32,
+    srchash: [u8; 32],
+    now: u32,
+) {
+    conn.execute(
+        "INSERT INTO notary_heights (symbol, notary_id, height, srchash, updated)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT(symbol, notary_id) DO UPDATE
+        SET height = excluded.height, srchash = excluded.srchash, updated = excluded.updated
+        WHERE excluded.height >= notary_heights.height",
+        params![symbol, notary_id, height, hex::encode(srchash), now],
+    )
+    .unwrap();
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct NotaryLag {
+    pub name: String,
+    pub height: u32,
+    // blocks behind the tip
+    pub behind: u32,
+    // when the notary last reported a height for the symbol
+    pub updated: u32,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct ChainTip {
+    pub symbol: String,
+    // highest height enough notaries reported, see get_chain_tip
+    pub height: u32,
+    // the srchash most notaries at that height reported
+    pub srchash: String,
+    // notaries at the tip height with that srchash
+    pub agreeing: usize,
+    // notaries that reported a height for the symbol
+    pub notaries: usize,
+    // notaries below the tip height, furthest behind first
+    pub lagging: Vec<NotaryLag>,
+    // notaries above the tip height, too few to move it
+    pub ahead: Vec<String>,
+}
+
+pub fn get_chain_tip(conn: &Connection, symbol: &str) -> Option<ChainTip> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT n.name, h.height, h.srchash, h.updated FROM notary_heights h
+            JOIN notaries n ON n.id = h.notary_id
+            WHERE h.symbol = ? ORDER BY h.height, n.id",
+        )
+        .unwrap();
+    let rows: Vec<(String, u32, String, u32)> = stmt
+        .query_map(params![symbol], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .unwrap()
+        .filter_map(|row| row.ok())
+        .collect();
+
+    // the highest height at least DPOW_MINSIGS notaries, or a majority of fewer, are at or
+    // above, so a single notary reporting a bogus height doesn't move the tip
+    let required = (rows.len() / 2 + 1).min(DPOW_MINSIGS);
+    let height = rows.get(rows.len().checked_sub(required)?)?.1;
+    let mut votes: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, _, srchash, _) in rows.iter().filter(|row| row.1 == height) {
+        *votes.entry(srchash).or_insert(0) += 1;
+    }
+    let (srchash, agreeing) = votes.into_iter().max_by_key(|(_, count)| *count)?;
+    Some(ChainTip {
+        symbol: symbol.to_string(),
+        height,
+        srchash: srchash.to_string(),
+        agreeing,
+        notaries: rows.len(),
+        lagging: rows
+            .iter()
+            .filter(|row| row.1 < height)
+            .map(|(name, notary_height, _, updated)| NotaryLag {
+                name: name.clone(),
+                height: *notary_height,
+                behind: height - notary_height,
+                updated: *updated,
+            })
+            .collect(),
+        ahead: rows
+            .iter()
+            .filter(|row| row.1 > height)
+            .map(|row| row.0.clone())
+            .collect(),
+    })
+}
+
+pub fn get_chain_tips(conn: &Connection) -> Vec<ChainTip> {
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT symbol FROM notary_heights ORDER BY symbol")
+        .unwrap();
+    let symbols: Vec<String> = stmt
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .filter_map(|row| row.ok())
+        .collect();
+    symbols
+        .iter()
+        .filter_map(|symbol| get_chain_tip(conn, symbol))
+        .collect()
+}
+
+// the UTXOs notaries offered per round, txid and vout are NULL where a notary offered none
+pub fn init_notary_utxos_table(conn: &Connection) {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS notary_utxos (
+        symbol TEXT NOT NULL,
+        height INTEGER NOT NULL,
+        notary_id INTEGER,
+        phase TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        txid TEXT,
+        vout INTEGER,
+        time INTEGER,
+        prev_symbol TEXT,
+        prev_height INTEGER,
+        prev_notary_id INTEGER,
+        PRIMARY KEY(symbol, height, notary_id, phase, kind),
+        FOREIGN KEY(notary_id) REFERENCES notaries(id)
+        )",
+        params![],
+    )
+    .unwrap();
+}
+
+// a round a UTXO was offered in, and by whom
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct UtxoUse {
+    pub symbol: String,
+    pub height: u32,
+    pub senderind: u8,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct NotaryUtxo {
+    pub symbol: String,
+    pub height: u32,
+    pub senderind: u8,
+    // "notarize" or "ratify"
+    pub phase: String,
+    // "src" or "dest"
+    pub kind: String,
+    // hex encoded as in the message, None if the notary offered no UTXO
+    pub txid: Option<String>,
+    pub vout: Option<u16>,
+    pub time: u32,
+    // the round another notary offered the same UTXO in before, if any
+    pub previous: Option<UtxoUse>,
+}
+
+// a notary changing its UTXO within a round replaces the one it offered before
+pub fn save_notary_utxo(conn: &Connection, utxo: &NotaryUtxo) {
+    let previous = utxo.previous.as_ref();
+    conn.execute(
+        "INSERT OR REPLACE INTO notary_utxos (symbol, height, notary_id, phase, kind, txid, vout,
+        time, prev_symbol, prev_height, prev_notary_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        params![
+            utxo.symbol,
+            utxo.height,
+            utxo.senderind,
+            utxo.phase,
+            utxo.kind,
+            utxo.txid,
+            utxo.vout,
+            utxo.time,
+            previous.map(|used| &used.symbol),
+            previous.map(|used| used.height),
+            previous.map(|used| used.senderind)
+        ],
+    )
+    .unwrap();
+}
+
+// most recent first; problems_only for UTXOs offered by more than one notary and rounds a
+// notary offered none in
+pub fn get_notary_utxos(
+    conn: &Connection,
+    symbol: Option<&str>,
+    problems_only: bool,
+    limit: u32,
+) -> Vec<NotaryUtxo> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT symbol, height, notary_id, phase, kind, txid, vout, time,
+            prev_symbol, prev_height, prev_notary_id FROM notary_utxos
+            WHERE (?1 IS NULL OR symbol = ?1)
+            AND (?2 = 0 OR txid IS NULL OR prev_symbol IS NOT NULL)
+            ORDER BY time DESC, height DESC, notary_id LIMIT ?3",
+        )
+        .unwrap();
+    let rows = stmt
+        .query_map(params![symbol, problems_only, limit], |row| {
+            let prev_symbol: Option<String> = row.get(8)?;
+            Ok(NotaryUtxo {
+                symbol: row.get(0)?,
+                height: row.get(1)?,
+                senderind: row.get(2)?,
+                phase: row.get(3)?,
+                kind: row.get(4)?,
+                txid: row.get(5)?,
+                vout: row.get(6)?,
+                time: row.get(7)?,
+                previous: match prev_symbol {
+                    Some(symbol) => Some(UtxoUse {
+                        symbol,
+                        height: row.get(9)?,
+                        senderind: row.get(10)?,
+                    }),
+                    None => None,
+                },
+            })
+        })
+        .unwrap();
+    rows.filter_map(|row| row.ok()).collect()
+}
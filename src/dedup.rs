@@ -0,0 +1,94 @@
+use crate::config::DedupConfig;
+use crate::now_sec;
+use std::collections::{HashMap, VecDeque};
+
+pub const DEFAULT_SEEN_CAPACITY: usize = 65536;
+// iguana only resends a round's messages for a few minutes
+pub const DEFAULT_SEEN_MAX_AGE: u32 = 600;
+
+// the most recently seen packethashes, forgetting the oldest once full or once they are
+// older than max_age seconds
+pub struct SeenPackets {
+    capacity: usize,
+    max_age: Option<u32>,
+    // in the order first seen, with the time they were seen
+    order: VecDeque<([u8; 32], u32)>,
+    seen: HashMap<[u8; 32], u32>,
+}
+
+impl Default for SeenPackets {
+    fn default() -> Self {
+        SeenPackets::new(DEFAULT_SEEN_CAPACITY).with_max_age(DEFAULT_SEEN_MAX_AGE)
+    }
+}
+
+impl SeenPackets {
+    pub fn new(capacity: usize) -> Self {
+        SeenPackets {
+            capacity,
+            max_age: None,
+            order: VecDeque::with_capacity(capacity),
+            seen: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn from_config(config: &DedupConfig) -> Self {
+        SeenPackets::new(config.capacity).with_max_age(config.max_age_secs)
+    }
+
+    pub fn with_max_age(mut self, max_age: u32) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // true if packethash was not seen before
+    pub fn insert(&mut self, packethash: [u8; 32]) -> bool {
+        self.insert_at(packethash, now_sec())
+    }
+
+    // true if packethash was seen and has not yet been forgotten at `now`, without inserting it
+    pub fn contains_at(&self, packethash: &[u8; 32], now: u32) -> bool {
+        match self.seen.get(packethash) {
+            Some(seen_at) => match self.max_age {
+                Some(max_age) => now.saturating_sub(*seen_at) <= max_age,
+                None => true,
+            },
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, packethash: &[u8; 32]) -> bool {
+        self.contains_at(packethash, now_sec())
+    }
+
+    // as insert, for packets received at `now` (unix seconds), eg when replaying a capture
+    pub fn insert_at(&mut self, packethash: [u8; 32], now: u32) -> bool {
+        if let Some(max_age) = self.max_age {
+            while let Some((oldest, seen_at)) = self.order.front() {
+                if now.saturating_sub(*seen_at) <= max_age {
+                    break;
+                }
+                self.seen.remove(oldest);
+                self.order.pop_front();
+            }
+        }
+        if self.seen.contains_key(&packethash) {
+            return false;
+        }
+        self.seen.insert(packethash, now);
+        self.order.push_back((packethash, now));
+        if self.order.len() > self.capacity {
+            let (oldest, _) = self.order.pop_front().unwrap();
+            self.seen.remove(&oldest);
+        }
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+}
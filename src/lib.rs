@@ -6,7 +6,32 @@ use serde_big_array::BigArray;
 use sha2::{Digest, Sha256};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod bestk;
+pub mod broadcast;
+pub mod capture;
+pub mod config;
 pub mod db;
+pub mod dedup;
+pub mod listener;
+pub mod logging;
+pub mod message;
+pub mod metrics;
+pub mod notaries;
+pub mod notarisation;
+pub mod packet;
+pub mod participation;
+pub mod peers;
+pub mod pipeline;
+pub mod ratelimit;
+pub mod relay;
+pub mod rounds;
+pub mod rpc;
+pub mod simulator;
+pub mod sp;
+pub mod storage;
+pub mod subscriptions;
+pub mod transport;
+pub mod utxos;
 
 pub const DPOW_SIGCHANNEL: u32 =
     b's' as u32 | (b'i' as u32) << 8 | (b'g' as u32) << 16 | (b's' as u32) << 24;
@@ -14,6 +39,8 @@ pub const DPOW_SIGBTCCHANNEL: u32 = !DPOW_SIGCHANNEL;
 pub const DPOW_TXIDCHANNEL: u32 =
     b't' as u32 | (b'x' as u32) << 8 | (b'i' as u32) << 16 | (b'd' as u32) << 24;
 pub const DPOW_BTCTXIDCHANNEL: u32 = !DPOW_TXIDCHANNEL;
+// signatures needed for a notarisation
+pub const DPOW_MINSIGS: usize = 13;
 
 pub const FIRST_PARTY: [&str; 64] = [
     "blackice_DEV",
@@ -136,6 +163,19 @@ pub struct DpowNanoMsgHdr {
     pub version1: u8,
 }
 
+impl DpowNanoMsgHdr {
+    // symbol is a nul padded C string
+    pub fn symbol_str(&self) -> String {
+        let end = self.symbol.iter().position(|b| *b == 0).unwrap_or(self.symbol.len());
+        String::from_utf8_lossy(&self.symbol[..end]).into_owned()
+    }
+}
+
+// masks are little endian uint64_t in iguana; bit n is set for notary n
+pub fn mask_to_u64(mask: &[u8; 8]) -> u64 {
+    u64::from_le_bytes(*mask)
+}
+
 pub fn now_sec() -> u32 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -187,17 +227,17 @@ pub fn validate_packet_signature(header: &IguanaPacketHeader) -> Result<PublicKe
     // recovery id is always 0 in iguana
     let recovery_id = RecoveryId::from_i32(0).unwrap();
     let recoverable_signature =
-        RecoverableSignature::from_compact(&header.sig, recovery_id).unwrap();
+        RecoverableSignature::from_compact(&header.sig, recovery_id).map_err(|_| ())?;
     let message = Message::from_slice(&header.packethash).unwrap();
 
     // Recover the public key
     let public_key = secp
         .recover_ecdsa(&message, &recoverable_signature)
-        .unwrap();
-    let signature = Signature::from_compact(&header.sig).unwrap();
+        .map_err(|_| ())?;
+    let signature = Signature::from_compact(&header.sig).map_err(|_| ())?;
 
     secp.verify_ecdsa(&message, &signature, &public_key)
-        .unwrap();
+        .map_err(|_| ())?;
     Ok(public_key)
 }
 
@@ -205,9 +245,17 @@ pub fn produce_packethash_signature(packethash: [u8; 32], sk: &SecretKey) -> Res
     let secp = Secp256k1::new();
     let message = Message::from_slice(&packethash).unwrap();
 
-    // iguana does sign_ecdsa_recoverable_with_noncedata but this is not neccesary for our purposes
-    let signature = secp.sign_ecdsa_recoverable(&message, &sk);
-
-    let (_recovery_id, sig) = signature.serialize_compact();
-    Ok(sig)
+    // the recovery id isn't sent, receivers assume 0, so like iguana keep trying different
+    // nonce data until the signature has recovery id 0
+    let mut signature = secp.sign_ecdsa_recoverable(&message, &sk);
+    let mut noncedata = [0u8; 32];
+    for i in 0..u32::MAX {
+        let (recovery_id, sig) = signature.serialize_compact();
+        if recovery_id.to_i32() == 0 {
+            return Ok(sig);
+        }
+        noncedata[..4].copy_from_slice(&i.to_le_bytes());
+        signature = secp.sign_ecdsa_recoverable_with_noncedata(&message, &sk, &noncedata);
+    }
+    Err(())
 }
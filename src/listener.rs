@@ -0,0 +1,297 @@
+use crate::broadcast::Broadcaster;
+use crate::capture::CaptureWriter;
+use crate::config::TransportKind;
+use crate::dedup::SeenPackets;
+use crate::metrics::Metrics;
+use crate::notaries::NotarySet;
+use crate::packet::DecodeLimits;
+use crate::peers::{PeerRequest, Peers};
+use crate::pipeline::{connect_to_ip, Pipeline};
+use crate::ratelimit::RateLimiter;
+use crate::rounds::Rounds;
+use crate::sp::DEFAULT_RECEIVE_MAX_SIZE;
+use crate::storage::Storage;
+use crate::subscriptions::Subscriptions;
+use crate::transport::{open_bus, Transport};
+use rusqlite::Connection;
+use std::io;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+pub use crate::pipeline::MessageHandler;
+
+// how often the listener wakes up to apply peer commands when the bus is quiet
+pub const DEFAULT_RECEIVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+// a complete iguana listener for embedding in other programs, eg
+// let listener = Listener::builder()
+//     .bind("1.2.3.4", "7775")
+//     .peer("5.6.7.8")
+//     .storage(Connection::open("iguana.db")?)
+//     .handler(Box::new(my_handler))
+//     .build()?;
+// thread::spawn(move || listener.run());
+pub struct Listener<S: Storage = Connection> {
+    pipeline: Pipeline<S>,
+    transport: Box<dyn Transport>,
+    peers: Peers,
+    bootstrap_peers: Vec<String>,
+    peer_commands: Sender<PeerRequest>,
+    peer_requests: Receiver<PeerRequest>,
+    capture: Option<CaptureWriter>,
+    broadcaster: Option<Broadcaster>,
+}
+
+impl Listener {
+    pub fn builder<S: Storage>() -> ListenerBuilder<S> {
+        ListenerBuilder::default()
+    }
+}
+
+impl<S: Storage> Listener<S> {
+    // for the RPC peer methods; commands are applied by the thread running the listener
+    pub fn peer_commands(&self) -> Sender<PeerRequest> {
+        self.peer_commands.clone()
+    }
+
+    pub fn rounds(&self) -> Arc<Mutex<Rounds>> {
+        self.pipeline.rounds.clone()
+    }
+
+    pub fn subscriptions(&self) -> Subscriptions {
+        self.pipeline.subscriptions.clone()
+    }
+
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.pipeline.metrics.clone()
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.pipeline.storage
+    }
+
+    // dials the bootstrap peers and every known IP, then processes messages until
+    // the transport ends or fails. failing to dial a bootstrap peer is a NotConnected
+    // error, before anything is received
+    pub fn run(mut self) -> io::Result<()> {
+        for peer in self.bootstrap_peers.iter() {
+            self.peers
+                .connect(self.transport.as_mut(), peer)
+                .map_err(|err| {
+                    io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        format!("cannot connect to bootstrap peer {}: {}", peer, err),
+                    )
+                })?;
+        }
+        for ip in self.pipeline.storage.known_ips() {
+            if !self.peers.is_banned(&ip) {
+                connect_to_ip(&mut self.peers, self.transport.as_mut(), &ip);
+            }
+        }
+        self.pipeline
+            .metrics
+            .set_connected_peers(self.peers.connected_count());
+
+        self.pipeline.run(
+            self.transport.as_mut(),
+            &mut self.peers,
+            &self.peer_requests,
+            self.capture.as_mut(),
+            self.broadcaster.as_mut(),
+        )
+    }
+}
+
+pub struct ListenerBuilder<S: Storage = Connection> {
+    bind: Option<(String, String)>,
+    transport_kind: TransportKind,
+    transport: Option<Box<dyn Transport>>,
+    receive_timeout: Duration,
+    receive_max_size: usize,
+    limits: DecodeLimits,
+    peers: Vec<String>,
+    notaries: NotarySet,
+    storage: Option<S>,
+    handlers: Vec<Box<dyn MessageHandler>>,
+    rounds: Option<Arc<Mutex<Rounds>>>,
+    subscriptions: Option<Subscriptions>,
+    metrics: Option<Arc<Metrics>>,
+    capture: Option<CaptureWriter>,
+    broadcaster: Option<Broadcaster>,
+    seen: Option<SeenPackets>,
+    rate_limiter: Option<RateLimiter>,
+}
+
+impl<S: Storage> Default for ListenerBuilder<S> {
+    fn default() -> Self {
+        ListenerBuilder {
+            bind: None,
+            transport_kind: TransportKind::default(),
+            transport: None,
+            receive_timeout: DEFAULT_RECEIVE_TIMEOUT,
+            receive_max_size: DEFAULT_RECEIVE_MAX_SIZE,
+            limits: DecodeLimits::default(),
+            peers: vec![],
+            notaries: NotarySet::default(),
+            storage: None,
+            handlers: vec![],
+            rounds: None,
+            subscriptions: None,
+            metrics: None,
+            capture: None,
+            broadcaster: None,
+            seen: None,
+            rate_limiter: None,
+        }
+    }
+}
+
+impl<S: Storage> ListenerBuilder<S> {
+    // the port is also the one dialled on every peer, as iguana uses the same port everywhere
+    pub fn bind(mut self, ip: &str, port: &str) -> Self {
+        self.bind = Some((ip.to_string(), port.to_string()));
+        self
+    }
+
+    pub fn transport_kind(mut self, kind: TransportKind) -> Self {
+        self.transport_kind = kind;
+        self
+    }
+
+    // use an already set up transport instead of binding one. peers are still dialled on
+    // the port given to bind(), or on iguana's 7775 without one
+    pub fn transport(mut self, transport: Box<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn receive_timeout(mut self, timeout: Duration) -> Self {
+        self.receive_timeout = timeout;
+        self
+    }
+
+    // larger bus messages are dropped by the socket; only used when binding a transport
+    pub fn receive_max_size(mut self, size: usize) -> Self {
+        self.receive_max_size = size;
+        self
+    }
+
+    pub fn decode_limits(mut self, limits: DecodeLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // a bootstrap peer, dialled when the listener starts
+    pub fn peer(mut self, ip: &str) -> Self {
+        self.peers.push(ip.to_string());
+        self
+    }
+
+    pub fn notaries(mut self, notaries: NotarySet) -> Self {
+        self.notaries = notaries;
+        self
+    }
+
+    pub fn storage(mut self, storage: S) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn handler(mut self, handler: Box<dyn MessageHandler>) -> Self {
+        self.handlers.push(handler);
+        self
+    }
+
+    // share round state, subscriptions or metrics with an RPC server set up beforehand
+    pub fn rounds(mut self, rounds: Arc<Mutex<Rounds>>) -> Self {
+        self.rounds = Some(rounds);
+        self
+    }
+
+    pub fn subscriptions(mut self, subscriptions: Subscriptions) -> Self {
+        self.subscriptions = Some(subscriptions);
+        self
+    }
+
+    pub fn metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    pub fn capture(mut self, capture: CaptureWriter) -> Self {
+        self.capture = Some(capture);
+        self
+    }
+
+    // take part in the bus by sending our own signed messages
+    pub fn broadcaster(mut self, broadcaster: Broadcaster) -> Self {
+        self.broadcaster = Some(broadcaster);
+        self
+    }
+
+    // replaces the default duplicate suppression cache
+    pub fn dedup(mut self, seen: SeenPackets) -> Self {
+        self.seen = Some(seen);
+        self
+    }
+
+    // track message rates per sender, recording incidents in storage
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn build(self) -> Result<Listener<S>, String> {
+        let storage = self.storage.ok_or("a storage backend is required")?;
+        let port = self
+            .bind
+            .as_ref()
+            .map_or("7775".to_string(), |(_, port)| port.clone());
+
+        let transport = match (self.transport, &self.bind) {
+            (Some(transport), _) => transport,
+            (None, Some((ip, port))) => {
+                let url = format!("tcp://{}:{}", ip, port);
+                open_bus(
+                    self.transport_kind,
+                    Some(&url),
+                    self.receive_timeout,
+                    self.receive_max_size,
+                )
+                .map_err(|err| format!("cannot bind to {}: {}", url, err))?
+            }
+            (None, None) => return Err("a bind address or transport is required".to_string()),
+        };
+
+        let peers = Peers::new(&storage, &port);
+        let mut pipeline = Pipeline::new(
+            storage,
+            self.rounds.unwrap_or_default(),
+            self.subscriptions.unwrap_or_default(),
+            self.metrics.unwrap_or_default(),
+        );
+        pipeline.notaries = self.notaries;
+        pipeline.limits = self.limits;
+        if let Some(seen) = self.seen {
+            pipeline.seen = Mutex::new(seen);
+        }
+        pipeline.rate_limiter = self.rate_limiter.map(Mutex::new);
+        for handler in self.handlers {
+            pipeline.add_handler(handler);
+        }
+
+        let (peer_commands, peer_requests) = channel();
+        Ok(Listener {
+            pipeline,
+            transport,
+            peers,
+            bootstrap_peers: self.peers,
+            peer_commands,
+            peer_requests,
+            capture: self.capture,
+            broadcaster: self.broadcaster,
+        })
+    }
+}
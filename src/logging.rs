@@ -0,0 +1,126 @@
+use crate::config::{LogFormat, LoggingConfig};
+use crate::now_sec;
+use log::kv::{self, Key, Value, VisitSource};
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_json::{Map, Number};
+use std::io::Write;
+use std::str::FromStr;
+
+// stdout logger with per-module level filters and optional JSON lines output
+pub struct Logger {
+    level: LevelFilter,
+    // (module prefix, level), longest prefix first
+    filters: Vec<(String, LevelFilter)>,
+    format: LogFormat,
+}
+
+impl Logger {
+    pub fn new(config: &LoggingConfig) -> Result<Self, String> {
+        let mut filters = config
+            .filters
+            .iter()
+            .map(|(module, level)| Ok((module.clone(), parse_level(level)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        filters.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(Logger {
+            level: parse_level(&config.level)?,
+            filters,
+            format: config.format,
+        })
+    }
+
+    // the most verbose level any module may log at
+    pub fn max_level(&self) -> LevelFilter {
+        self.filters
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.level, std::cmp::max)
+    }
+
+    fn target_level(&self, target: &str) -> LevelFilter {
+        self.filters
+            .iter()
+            .find(|(module, _)| target == module || target.starts_with(&format!("{}::", module)))
+            .map_or(self.level, |(_, level)| *level)
+    }
+
+    pub fn format(&self, record: &Record) -> String {
+        let mut fields = Fields(Vec::new());
+        let _ = record.key_values().visit(&mut fields);
+
+        match self.format {
+            LogFormat::Text => {
+                let mut line = format!(
+                    "{} {:5} {}: {}",
+                    now_sec(),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                );
+                for (key, value) in fields.0 {
+                    line.push_str(&format!(" {}={}", key, value));
+                }
+                line
+            }
+            LogFormat::Json => {
+                let mut object = Map::new();
+                object.insert("ts".to_string(), now_sec().into());
+                object.insert("level".to_string(), record.level().as_str().into());
+                object.insert("target".to_string(), record.target().into());
+                object.insert("msg".to_string(), record.args().to_string().into());
+                for (key, value) in fields.0 {
+                    object.insert(key, value);
+                }
+                serde_json::Value::Object(object).to_string()
+            }
+        }
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    LevelFilter::from_str(level).map_err(|_| format!("invalid log level '{}'", level))
+}
+
+// collects a record's key-values as JSON values
+struct Fields(Vec<(String, serde_json::Value)>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let json = if let Some(n) = value.to_u64() {
+            n.into()
+        } else if let Some(n) = value.to_i64() {
+            n.into()
+        } else if let Some(b) = value.to_bool() {
+            b.into()
+        } else if let Some(n) = value.to_f64().and_then(Number::from_f64) {
+            serde_json::Value::Number(n)
+        } else {
+            value.to_string().into()
+        };
+        self.0.push((key.as_str().to_string(), json));
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.target_level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            let line = self.format(record);
+            let _ = writeln!(std::io::stdout().lock(), "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stdout().flush();
+    }
+}
+
+pub fn init(config: &LoggingConfig) -> Result<(), String> {
+    let logger = Logger::new(config)?;
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(logger)).map_err(|err| err.to_string())
+}
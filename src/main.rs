@@ -1,183 +1,267 @@
-use bincode::Options;
-use nanomsg::{Protocol, Socket};
-
 use std::env;
-use std::io::Read;
+use std::sync::mpsc::channel;
 use std::thread;
 
-use iguana_rs::{
-    validate_packet_signature, validate_packethash, DpowNanoMsgHdr, IguanaPacketHeader, FIRST_PARTY,
-};
-
 // TODO: cleanup all db OPs into other file
-use iguana_rs::db::{init_db, update_ip_logs, update_known_ips, update_lastseen};
+use iguana_rs::db::init_db;
+use iguana_rs::dedup::SeenPackets;
+use iguana_rs::rounds::Rounds;
+use iguana_rs::peers::{PeerRequest, Peers};
+use iguana_rs::broadcast::Broadcaster;
+use iguana_rs::capture::{CaptureReader, CaptureWriter};
+use iguana_rs::config::Config;
+use iguana_rs::listener::Listener;
+use iguana_rs::logging;
+use iguana_rs::metrics::{serve_metrics, Metrics};
+use iguana_rs::notaries::NotarySet;
+use iguana_rs::participation::{notary_participation, Participation};
+use iguana_rs::pipeline::Pipeline;
+use iguana_rs::ratelimit::RateLimiter;
+use iguana_rs::relay::Relay;
+use iguana_rs::transport::{open_bus, ReplayTransport};
+use iguana_rs::subscriptions::{start_ws_server, Subscriptions};
+use iguana_rs::rpc::{
+    add_admin_method, add_peer_methods, add_query_methods, add_round_methods, start_rpc_server,
+    RpcHandler,
+};
+use log::{error, info};
 use rusqlite::Connection;
 
 use jsonrpc_core::types::error::Error;
 use jsonrpc_core::types::params::Params;
 use jsonrpc_core::types::Value;
-use jsonrpc_core::IoHandler;
-use jsonrpc_http_server::ServerBuilder;
 //use jsonrpc_http_server::*;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use futures::future;
+// how long the relay waits on one side before checking the other
+const RELAY_RECEIVE_TIMEOUT: Duration = Duration::from_millis(20);
 
-fn print_hex(bytes: &[u8]) {
-    for b in bytes {
-        print!("{:02x}", b);
+fn notaries(config: &Config) -> NotarySet {
+    match &config.notaries {
+        Some(notaries) => NotarySet::from_config(notaries).unwrap(),
+        None => NotarySet::default(),
     }
-    println!("");
 }
-use std::net::Ipv4Addr;
-
-fn reverse_bits(byte: u8) -> u8 {
-    let mut v = byte;
-    let mut r = v;
-    let mut s = 7;
-    v >>= 1;
-    while v != 0 {
-        r <<= 1;
-        r |= v & 1;
-        v >>= 1;
-        s -= 1;
+
+// usage ./iguana_rs_listener replay <capture file> <db filename> [config file] [--realtime]
+// feeds a capture file through the same pipeline as live traffic, as fast as possible
+// unless --realtime is given, in which case the original gaps between messages are kept
+fn replay(args: &[String]) {
+    let realtime = args.iter().any(|arg| arg == "--realtime");
+    let args: Vec<&String> = args.iter().filter(|arg| *arg != "--realtime").collect();
+    if args.len() < 2 {
+        eprintln!("usage: iguana_rs_listener replay <capture file> <db filename> [config file] [--realtime]");
+        std::process::exit(1);
     }
-    r <<= s;
-    r
-}
 
-fn printinfo(dpow_msg: &DpowNanoMsgHdr) {
-    println!(
-        "{} {:?} {} {} channel:{:?} bestk:{}",
-        FIRST_PARTY[dpow_msg.senderind as usize],
-        dpow_msg.myipbits,
-        std::str::from_utf8(&dpow_msg.symbol).unwrap(),
-        dpow_msg.height,
-        dpow_msg.channel,
-        dpow_msg.notarize.bestk,
+    let config = match args.get(2) {
+        Some(path) => Config::load(path).unwrap(),
+        None => Config::default(),
+    };
+    logging::init(&config.logging).unwrap();
+
+    let conn = Connection::open(args[1]).unwrap();
+    init_db(&conn);
+    let mut pipeline = Pipeline::new(
+        conn,
+        Arc::new(Mutex::new(Rounds::new())),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
     );
-    print!("bestmask:");
-    for byte in &dpow_msg.notarize.bestmask {
-        print!("{:08b}", reverse_bits(*byte));
-    }
-    println!();
-    print!("recvmask:");
-    for byte in &dpow_msg.notarize.recvmask {
-        print!("{:08b}", reverse_bits(*byte));
+    pipeline.notaries = notaries(&config);
+    pipeline.limits = config.limits.decode_limits();
+    pipeline.seen = Mutex::new(SeenPackets::from_config(&config.dedup));
+    pipeline.rate_limiter = config
+        .rate_limit
+        .as_ref()
+        .map(|rate_limit| {
+            Mutex::new(RateLimiter::new(rate_limit).expect("invalid rate_limit config"))
+        });
+
+    // there is nothing to connect to, but peer commands still need a channel
+    let reader = CaptureReader::open(args[0])
+        .expect("cannot open capture file")
+        .with_max_len(config.limits.max_message_size);
+    let mut transport = ReplayTransport::new(reader, realtime);
+    let mut peers = Peers::new(&pipeline.storage, "0");
+    let (_peer_commands, peer_requests) = channel::<PeerRequest>();
+    match pipeline.run(&mut transport, &mut peers, &peer_requests, None, None) {
+        Ok(()) => info!("finished replaying {}", args[0]),
+        Err(err) => error!("stopping replay, bad capture record: {}", err),
     }
-    println!();
-    print!("srchash:");
-    print_hex(&dpow_msg.srchash);
-    print!("desthash:");
-    print_hex(&dpow_msg.desthash);
 }
 
-fn connect_to_ip(socket: &mut Socket, ip: &String, port: &String ) {
-    let dial = format!("tcp://{}:{}", ip, port);
-    let _out_endpoint = match socket.connect(&dial) {
-        Ok(_) => {
-            println!("connect to {}", dial);
+// usage ./iguana_rs_listener relay <url to bind to> <peer urls, comma separated> [config file]
+// forwards valid packets between the two networks without storing anything, eg
+// relay tcp://10.0.0.1:7775 tcp://1.2.3.4:7775,tcp://5.6.7.8:7775
+fn relay(args: &[String]) {
+    if args.len() < 2 {
+        eprintln!("usage: iguana_rs_listener relay <url to bind to> <peer urls, comma separated> [config file]");
+        std::process::exit(1);
+    }
+    let config = match args.get(2) {
+        Some(path) => Config::load(path).unwrap(),
+        None => Config::default(),
+    };
+    logging::init(&config.logging).unwrap();
+
+    let max_size = config.limits.max_message_size;
+    let bound = open_bus(
+        config.transport,
+        Some(&args[0]),
+        RELAY_RECEIVE_TIMEOUT,
+        max_size,
+    )
+    .expect("cannot bind relay socket");
+    let mut connected = open_bus(config.transport, None, RELAY_RECEIVE_TIMEOUT, max_size)
+        .expect("cannot open relay socket");
+    for peer in args[1].split(',') {
+        connected.connect(peer).expect("cannot connect to relay peer");
+    }
+
+    let mut relay = match Relay::new(bound, connected, notaries(&config)) {
+        Ok(relay) => relay,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
         }
-        Err(_) => println!("failed connect to {}", dial),
     };
+    relay.limits = config.limits.decode_limits();
+    info!("relaying between {} and {}", args[0], args[1]);
+    if let Err(err) = relay.run() {
+        error!("relay stopped: {}", err);
+    }
 }
 
-fn connect_to_known_ips(conn: &Connection, socket: &mut Socket, server_port: &String) {
-    let mut stmt = conn.prepare("SELECT ip FROM ipbits").unwrap();
-
-    let rows = stmt.query_map([], |row| {
-        let ip: String = row.get(0)?;
-        Ok(ip)
-    }).unwrap();
-
-    for ip_result in rows {
-        if let Ok(ip) = ip_result {
-            connect_to_ip(socket, &ip, server_port);
+// usage ./iguana_rs_listener report <db filename> [from unix time] [to unix time]
+// prints how each notary took part in the rounds started in the range, overall and per symbol
+fn report(args: &[String]) {
+    if args.is_empty() {
+        eprintln!("usage: iguana_rs_listener report <db filename> [from unix time] [to unix time]");
+        std::process::exit(1);
+    }
+    let from = args.get(1).map(|from| from.parse().expect("bad from time")).unwrap_or(0);
+    let to = args.get(2).map(|to| to.parse().expect("bad to time")).unwrap_or(u32::MAX);
+
+    let conn = Connection::open(&args[0]).unwrap();
+    init_db(&conn);
+    let print = |name: &str, stats: &Participation| {
+        let first_message = match stats.avg_first_message_secs {
+            Some(secs) => format!("{:.1}s", secs),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<24} {:>7} {:>10.1}% {:>7} {:>14}",
+            name, stats.rounds, stats.bestmask_pct, stats.absent, first_message
+        );
+    };
+    println!(
+        "{:<24} {:>7} {:>11} {:>7} {:>14}",
+        "notary", "rounds", "bestmask", "absent", "first message"
+    );
+    for notary in notary_participation(&conn, from, to) {
+        // leave out notaries that sent nothing in the range
+        if notary.total.rounds == notary.total.absent {
+            continue;
+        }
+        print(&notary.name, &notary.total);
+        for (symbol, stats) in notary.symbols.iter() {
+            print(&format!("  {}", symbol), stats);
         }
     }
 }
 
-// usage ./iguana_rs_listener <external IP to bind to> <port to bind to> <initial peer to connect to> <db filename>
+// usage ./iguana_rs_listener <external IP to bind to> <port to bind to> <initial peer to connect to> <db filename> [config file]
 fn main() {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        replay(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("relay") {
+        relay(&args[2..]);
+        return;
+    }
+    if args.get(1).map(String::as_str) == Some("report") {
+        report(&args[2..]);
+        return;
+    }
+
+    let config = match args.get(5) {
+        Some(path) => Config::load(path).unwrap(),
+        None => Config::default(),
+    };
+    logging::init(&config.logging).unwrap();
 
     let server_ip = args[1].clone();
     let server_port = args[2].clone();
-    let server_url = format!("tcp://{}:{}", server_ip, server_port);
 
     let bootstrap_peer = args[3].clone();
-    let peer_string = format!("tcp://{}:{}", bootstrap_peer, server_port);
 
     let db_file = args[4].clone();
 
-    let binconf = bincode::DefaultOptions::new().with_fixint_encoding();
-
-    let mut in_socket = Socket::new(Protocol::Bus).expect("cannot create socket");
-    let _in_endpoint = in_socket.bind(&server_url).expect("cannot bind to socket");
-
-    let _out_endpoint = match in_socket.connect(&peer_string) {
-        Ok(ep) => ep,
-        Err(err) => panic!("Failed to connect socket: {}", err),
-    };
+    // separate connection for the RPC server so queries don't contend with the listener
+    let rpc_conn = Connection::open(&db_file).unwrap();
+    init_db(&rpc_conn);
+    let rpc_conn = Arc::new(Mutex::new(rpc_conn));
+
+    let conn = Connection::open(&db_file).unwrap();
+    init_db(&conn);
+    let mut builder = Listener::builder()
+        .bind(&server_ip, &server_port)
+        .transport_kind(config.transport)
+        .peer(&bootstrap_peer)
+        .notaries(notaries(&config))
+        .receive_max_size(config.limits.max_message_size)
+        .decode_limits(config.limits.decode_limits())
+        .dedup(SeenPackets::from_config(&config.dedup))
+        .storage(conn);
+    if let Some(capture_config) = &config.capture {
+        info!("capturing raw packets to {}", capture_config.path);
+        builder = builder
+            .capture(CaptureWriter::new(capture_config).expect("cannot open capture file"));
+    }
+    if let Some(rate_limit) = &config.rate_limit {
+        builder =
+            builder.rate_limiter(RateLimiter::new(rate_limit).expect("invalid rate_limit config"));
+    }
+    if let Some(broadcast_config) = &config.broadcast {
+        info!("broadcasting as notary {}", broadcast_config.senderind);
+        builder = builder.broadcaster(Broadcaster::new(broadcast_config, &server_ip).unwrap());
+    }
+    let listener = builder.build().unwrap();
+
+    let rounds = listener.rounds();
+    let subscriptions = listener.subscriptions();
+    let peer_commands = listener.peer_commands();
+    if let Some(metrics_config) = &config.metrics {
+        serve_metrics(&metrics_config.bind, listener.metrics(), rpc_conn.clone())
+            .expect("cannot bind metrics endpoint");
+        info!("metrics endpoint listening on {}", metrics_config.bind);
+    }
 
-    let mut buffer = vec![];
     //let mut connect_once = true;
     let connect_once = Arc::new(Mutex::new(true));
     let _connect_once_for_thread = connect_once.clone();
 
-    //let mut out_sockets : Vec<Socket> = Vec::new();
     thread::spawn(move || {
-        let conn = Connection::open(db_file).unwrap();
-        init_db(&conn);
-        connect_to_known_ips(&conn, &mut in_socket, &server_port);
-
-        loop {
-            match in_socket.read_to_end(&mut buffer) {
-                Ok(_mysize) => {
-                    loop {
-                        if buffer.len() == 0 {
-                            break;
-                        };
-
-                        let header: IguanaPacketHeader =
-                            binconf.deserialize(&buffer[..104]).unwrap();
-                        buffer = buffer[104..].to_vec();
-
-                        validate_packethash(&header, &buffer).unwrap();
-                        let _pubkey = validate_packet_signature(&header).unwrap();
-                        // TODO: add "validate_pubkey" flag
-
-                        let msg_size = std::mem::size_of::<DpowNanoMsgHdr>() - 1;
-                        let dpow_msg: DpowNanoMsgHdr =
-                            binconf.deserialize(&buffer[..msg_size]).unwrap();
-                        buffer = buffer[msg_size..].to_vec();
-
-                        update_lastseen(&conn, dpow_msg.senderind);
-                        update_ip_logs(&conn, dpow_msg.senderind, dpow_msg.myipbits);
-                        let new_ips = update_known_ips(&conn, dpow_msg.senderind, dpow_msg.ipbits.to_vec());
-                        for ip in new_ips.iter() {
-                            connect_to_ip(&mut in_socket, ip, &server_port);
-                        }
-                        printinfo(&dpow_msg);
-
-                        let _extra = &buffer[..dpow_msg.datalen as usize];
-
-                        buffer = buffer[dpow_msg.datalen as usize..].to_vec();
-                    }
-                }
-                Err(err) => {
-                    println!("Client failed to receive msg '{}'.", err);
-                    break;
-                }
+        if let Err(err) = listener.run() {
+            if err.kind() == std::io::ErrorKind::NotConnected {
+                error!("{}", err);
+            } else {
+                error!("Client failed to receive msg '{}'.", err);
             }
+            // nothing is listening any more
+            std::process::exit(1);
         }
     });
 
     thread::spawn(move || {
-        let mut io = IoHandler::default();
+        let mut io = RpcHandler::default();
         let connect_once_rpc = connect_once.clone(); // We clone here because the closure requires ownership
-        io.add_method("set_connect_once", move |params: Params| {
-            future::ready(match params {
+        add_admin_method(&mut io, "set_connect_once", move |params: Params| {
+            match params {
                 Params::Map(map) => {
                     if let Some(Value::Bool(val)) = map.get("value") {
                         *connect_once_rpc.lock().unwrap() = *val;
@@ -187,15 +271,22 @@ fn main() {
                     }
                 }
                 _ => Err(Error::invalid_params("Expected map")),
-            })
+            }
         });
+        add_query_methods(&mut io, rpc_conn);
+        add_round_methods(&mut io, rounds);
+        add_peer_methods(&mut io, peer_commands);
 
-        let server = ServerBuilder::new(io)
-            .threads(3)
-            .start_http(&"127.0.0.1:3030".parse().unwrap())
-            .unwrap();
+        let server = start_rpc_server(io, &config.rpc).unwrap();
+
+        // keep the ws server alive for as long as the RPC server
+        let _ws_server = config.ws.as_ref().map(|ws_config| {
+            let ws_server = start_ws_server(subscriptions, ws_config, &config.rpc).unwrap();
+            info!("WebSocket message stream listening on {}", ws_config.bind);
+            ws_server
+        });
 
-        println!("JSON-RPC server listening on 127.0.0.1:3030");
+        info!("JSON-RPC server listening on {}", config.rpc.bind);
         server.wait();
     });
 
@@ -0,0 +1,80 @@
+use crate::rounds::{format_mask, BESTK_NONE};
+use crate::{mask_to_u64, now_sec, DpowNanoMsgHdr, FIRST_PARTY};
+use serde::Serialize;
+use std::net::Ipv4Addr;
+
+// names of the notaries whose bits are set in a mask
+pub fn mask_notaries(mask: u64) -> Vec<String> {
+    FIRST_PARTY
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| mask & (1 << i) != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+pub fn notary_name(senderind: u8) -> String {
+    match FIRST_PARTY.get(senderind as usize) {
+        Some(name) => name.to_string(),
+        None => format!("unknown_{}", senderind),
+    }
+}
+
+// JSON friendly view of a DpowNanoMsgHdr with hex hashes and decoded masks
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct DecodedMessage {
+    pub received: u32,
+    pub sender: String,
+    pub senderind: u8,
+    pub myip: String,
+    pub symbol: String,
+    pub height: u32,
+    pub channel: u32,
+    pub bestk: Option<u8>,
+    pub bestmask: String,
+    pub bestmask_notaries: Vec<String>,
+    pub recvmask: String,
+    pub recvmask_notaries: Vec<String>,
+    pub srchash: String,
+    pub desthash: String,
+}
+
+impl DecodedMessage {
+    pub fn new(dpow_msg: &DpowNanoMsgHdr) -> Self {
+        let bestmask = mask_to_u64(&dpow_msg.notarize.bestmask);
+        let recvmask = mask_to_u64(&dpow_msg.notarize.recvmask);
+        DecodedMessage {
+            received: now_sec(),
+            sender: notary_name(dpow_msg.senderind),
+            senderind: dpow_msg.senderind,
+            myip: Ipv4Addr::from(dpow_msg.myipbits).to_string(),
+            symbol: dpow_msg.symbol_str(),
+            height: dpow_msg.height,
+            channel: dpow_msg.channel,
+            bestk: (dpow_msg.notarize.bestk != BESTK_NONE).then_some(dpow_msg.notarize.bestk),
+            bestmask: format_mask(bestmask),
+            bestmask_notaries: mask_notaries(bestmask),
+            recvmask: format_mask(recvmask),
+            recvmask_notaries: mask_notaries(recvmask),
+            srchash: hex::encode(dpow_msg.srchash),
+            desthash: hex::encode(dpow_msg.desthash),
+        }
+    }
+
+    // one log line per message, the fields are kept structured for JSON output
+    pub fn log(&self) {
+        log::info!(
+            sender = self.sender.as_str(),
+            myip = self.myip.as_str(),
+            symbol = self.symbol.as_str(),
+            height = self.height,
+            channel = self.channel,
+            bestk = self.bestk.map_or(-1, |bestk| bestk as i16),
+            bestmask = self.bestmask.as_str(),
+            recvmask = self.recvmask.as_str(),
+            srchash = self.srchash.as_str(),
+            desthash = self.desthash.as_str();
+            "{} {} {}", self.sender, self.symbol, self.height
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::db::get_notaries;
+use crate::now_sec;
+use crate::packet::PacketError;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// packethash_pow gives up after 10000 nonces
+const NONCE_BUCKETS: [f64; 9] = [1.0, 10.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0];
+const DB_WRITE_BUCKETS: [f64; 8] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.05, 0.25, 1.0];
+// symbols counted on their own in iguana_messages_total, the rest are counted as "other"
+pub const MAX_MESSAGE_SYMBOLS: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    bounds: Vec<f64>,
+    // cumulative counts per bound, as prometheus expects
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Histogram {
+            bounds: bounds.to_vec(),
+            buckets: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter_mut()) {
+            if value <= *bound {
+                *bucket += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bound, bucket) in self.bounds.iter().zip(self.buckets.iter()) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, bucket);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+// counters updated by the listener thread and rendered in the prometheus text format
+pub struct Metrics {
+    packets_received: AtomicU64,
+    packets_valid: AtomicU64,
+    packets_duplicate: AtomicU64,
+    connected_peers: AtomicU64,
+    packets_invalid: Mutex<BTreeMap<&'static str, u64>>,
+    // keyed by symbol then notary
+    messages: Mutex<BTreeMap<String, BTreeMap<String, u64>>>,
+    bestk_mismatches: Mutex<BTreeMap<String, u64>>,
+    nonces: Mutex<Histogram>,
+    db_writes: Mutex<Histogram>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            packets_received: AtomicU64::new(0),
+            packets_valid: AtomicU64::new(0),
+            packets_duplicate: AtomicU64::new(0),
+            connected_peers: AtomicU64::new(0),
+            packets_invalid: Mutex::new(BTreeMap::new()),
+            messages: Mutex::new(BTreeMap::new()),
+            bestk_mismatches: Mutex::new(BTreeMap::new()),
+            nonces: Mutex::new(Histogram::new(&NONCE_BUCKETS)),
+            db_writes: Mutex::new(Histogram::new(&DB_WRITE_BUCKETS)),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn packet_received(&self) {
+        self.packets_received.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_valid(&self, nonce: u32) {
+        self.packets_valid.fetch_add(1, Ordering::Relaxed);
+        self.nonces.lock().unwrap().observe(nonce as f64);
+    }
+
+    // a valid packet already processed when it arrived from another peer
+    pub fn packet_duplicate(&self) {
+        self.packets_duplicate.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn packet_invalid(&self, err: PacketError) {
+        *self
+            .packets_invalid
+            .lock()
+            .unwrap()
+            .entry(err.reason())
+            .or_insert(0) += 1;
+    }
+
+    pub fn message(&self, notary: &str, symbol: &str) {
+        let mut messages = self.messages.lock().unwrap();
+        let symbol = if messages.contains_key(symbol) || messages.len() < MAX_MESSAGE_SYMBOLS {
+            symbol
+        } else {
+            "other"
+        };
+        *messages
+            .entry(symbol.to_string())
+            .or_default()
+            .entry(notary.to_string())
+            .or_insert(0) += 1;
+    }
+
+    // a message whose bestk or bestmask doesn't follow from its recvmask
+    pub fn bestk_mismatch(&self, notary: &str) {
+        *self
+            .bestk_mismatches
+            .lock()
+            .unwrap()
+            .entry(notary.to_string())
+            .or_insert(0) += 1;
+    }
+
+    pub fn db_write(&self, elapsed: Duration) {
+        self.db_writes
+            .lock()
+            .unwrap()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn set_connected_peers(&self, peers: usize) {
+        self.connected_peers.store(peers as u64, Ordering::Relaxed);
+    }
+
+    // notary lastseen and known IPs are read from the db at scrape time
+    pub fn render(&self, conn: &Connection) -> String {
+        let mut out = String::new();
+
+        counter(
+            &mut out,
+            "iguana_packets_received_total",
+            "Packets read from the bus socket.",
+        );
+        let _ = writeln!(
+            out,
+            "iguana_packets_received_total {}",
+            self.packets_received.load(Ordering::Relaxed)
+        );
+        counter(
+            &mut out,
+            "iguana_packets_valid_total",
+            "Packets that passed validation.",
+        );
+        let _ = writeln!(
+            out,
+            "iguana_packets_valid_total {}",
+            self.packets_valid.load(Ordering::Relaxed)
+        );
+        let valid = self.packets_valid.load(Ordering::Relaxed);
+        let duplicate = self.packets_duplicate.load(Ordering::Relaxed);
+        counter(
+            &mut out,
+            "iguana_packets_duplicate_total",
+            "Valid packets dropped as already seen.",
+        );
+        let _ = writeln!(out, "iguana_packets_duplicate_total {}", duplicate);
+        gauge(
+            &mut out,
+            "iguana_packets_duplicate_ratio",
+            "Share of valid packets that were duplicates.",
+        );
+        let ratio = match valid + duplicate {
+            0 => 0.0,
+            total => duplicate as f64 / total as f64,
+        };
+        let _ = writeln!(out, "iguana_packets_duplicate_ratio {}", ratio);
+        counter(
+            &mut out,
+            "iguana_packets_invalid_total",
+            "Packets rejected, by reason.",
+        );
+        for (reason, count) in self.packets_invalid.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "iguana_packets_invalid_total{{reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+        counter(
+            &mut out,
+            "iguana_messages_total",
+            "Valid messages by notary and symbol.",
+        );
+        for (symbol, notaries) in self.messages.lock().unwrap().iter() {
+            for (notary, count) in notaries.iter() {
+                let _ = writeln!(
+                    out,
+                    "iguana_messages_total{{notary=\"{}\",symbol=\"{}\"}} {}",
+                    notary,
+                    escape_label(symbol),
+                    count
+                );
+            }
+        }
+        counter(
+            &mut out,
+            "iguana_bestk_mismatch_total",
+            "Messages with a bestk or bestmask iguana wouldn't choose for their recvmask.",
+        );
+        for (notary, count) in self.bestk_mismatches.lock().unwrap().iter() {
+            let _ = writeln!(
+                out,
+                "iguana_bestk_mismatch_total{{notary=\"{}\"}} {}",
+                notary, count
+            );
+        }
+
+        gauge(
+            &mut out,
+            "iguana_notary_last_seen_seconds",
+            "Seconds since a notary was last seen.",
+        );
+        let now = now_sec();
+        for notary in get_notaries(conn)
+            .iter()
+            .filter(|notary| notary.lastseen > 0)
+        {
+            let _ = writeln!(
+                out,
+                "iguana_notary_last_seen_seconds{{notary=\"{}\"}} {}",
+                notary.name,
+                now.saturating_sub(notary.lastseen)
+            );
+        }
+        gauge(&mut out, "iguana_known_ips", "IPs learned from ipbits.");
+        let known_ips: u64 = conn
+            .query_row("SELECT COUNT(*) FROM ipbits", [], |row| row.get(0))
+            .unwrap_or(0);
+        let _ = writeln!(out, "iguana_known_ips {}", known_ips);
+        gauge(
+            &mut out,
+            "iguana_connected_peers",
+            "Outgoing bus connections.",
+        );
+        let _ = writeln!(
+            out,
+            "iguana_connected_peers {}",
+            self.connected_peers.load(Ordering::Relaxed)
+        );
+
+        self.nonces.lock().unwrap().render(
+            &mut out,
+            "iguana_packet_nonce",
+            "PoW nonce of valid packets.",
+        );
+        self.db_writes.lock().unwrap().render(
+            &mut out,
+            "iguana_db_write_seconds",
+            "Time spent writing a message to the db.",
+        );
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge", name, help, name);
+}
+
+// symbols come straight off the wire
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// minimal HTTP server answering GET /metrics; anything else is a 404
+pub fn serve_metrics(
+    bind: &str,
+    metrics: Arc<Metrics>,
+    conn: Arc<Mutex<Connection>>,
+) -> std::io::Result<thread::JoinHandle<()>> {
+    let listener = TcpListener::bind(bind)?;
+    Ok(thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => continue,
+            };
+            let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+            let mut request_line = String::new();
+            if BufReader::new(&stream)
+                .read_line(&mut request_line)
+                .is_err()
+            {
+                continue;
+            }
+
+            let response = if request_line.starts_with("GET /metrics ") {
+                let body = metrics.render(&conn.lock().unwrap());
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    }))
+}
@@ -0,0 +1,103 @@
+use crate::config::NotaryConfig;
+use crate::packet::{Packet, PacketError};
+use crate::FIRST_PARTY;
+use secp256k1::PublicKey;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notary {
+    pub name: String,
+    // None accepts a signature from any key for this senderind
+    pub pubkey: Option<PublicKey>,
+}
+
+// the notaries a listener accepts messages from, indexed by senderind
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotarySet {
+    notaries: Vec<Notary>,
+}
+
+impl Default for NotarySet {
+    fn default() -> Self {
+        NotarySet::first_party()
+    }
+}
+
+impl NotarySet {
+    pub fn new(notaries: Vec<Notary>) -> Self {
+        NotarySet { notaries }
+    }
+
+    // the FIRST_PARTY names without pubkeys, which is what the listener has always checked
+    pub fn first_party() -> Self {
+        NotarySet::new(
+            FIRST_PARTY
+                .iter()
+                .map(|name| Notary {
+                    name: name.to_string(),
+                    pubkey: None,
+                })
+                .collect(),
+        )
+    }
+
+    pub fn from_config(notaries: &[NotaryConfig]) -> Result<Self, String> {
+        // masks are a u64 with a bit per notary, as in iguana
+        if notaries.len() > 64 {
+            return Err(format!(
+                "{} notaries configured, at most 64 are supported",
+                notaries.len()
+            ));
+        }
+        let notaries = notaries
+            .iter()
+            .map(|notary| {
+                let pubkey = match &notary.pubkey {
+                    Some(pubkey) => Some(
+                        hex::decode(pubkey)
+                            .ok()
+                            .and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+                            .ok_or(format!("invalid pubkey for notary {}", notary.name))?,
+                    ),
+                    None => None,
+                };
+                Ok(Notary {
+                    name: notary.name.clone(),
+                    pubkey,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(NotarySet::new(notaries))
+    }
+
+    pub fn len(&self) -> usize {
+        self.notaries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notaries.is_empty()
+    }
+
+    // notaries any key is accepted for
+    pub fn without_pubkey(&self) -> Vec<&str> {
+        self.notaries
+            .iter()
+            .filter(|notary| notary.pubkey.is_none())
+            .map(|notary| notary.name.as_str())
+            .collect()
+    }
+
+    pub fn get(&self, senderind: u8) -> Option<&Notary> {
+        self.notaries.get(senderind as usize)
+    }
+
+    // the senderind must be in the set and, if its pubkey is known, have signed the packet
+    pub fn check(&self, packet: &Packet) -> Result<(), PacketError> {
+        let notary = self
+            .get(packet.dpow_msg.senderind)
+            .ok_or(PacketError::UnknownSender)?;
+        match notary.pubkey {
+            Some(pubkey) if pubkey != packet.pubkey => Err(PacketError::PubkeyMismatch),
+            _ => Ok(()),
+        }
+    }
+}
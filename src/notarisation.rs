@@ -0,0 +1,213 @@
+use crate::rounds::BESTK_NONE;
+use crate::{mask_to_u64, DpowNanoMsgHdr};
+use chain::bytes::Bytes;
+use chain::hash::H256;
+use chain::{OutPoint, Transaction, TransactionInput, TransactionOutput};
+use serialization::serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// rebuilds the notarisation tx iguana's dpow_notarytx creates on the dest chain: one input
+// per notary in bestmask spending its destutxo, an output to CRYPTO777 and an OP_RETURN of
+// srchash, height and symbol. each notary's sig1 signs that tx for its own bestk and
+// bestmask, so the signed tx is only complete once every notary in bestmask has sent one
+
+// DPOW_UTXOSIZE, the value of the split UTXOs notaries spend
+pub const DPOW_UTXOSIZE: u64 = 10000;
+pub const CRYPTO777_PUBKEY: &str =
+    "020e46e79a2a8d12b9b5d12c7a91adb4e454edfae43c0a0cb805427d2ac7613fd9";
+
+const OP_PUSHDATA1: u8 = 0x4c;
+const OP_PUSHDATA2: u8 = 0x4d;
+const OP_PUSHDATA4: u8 = 0x4e;
+const OP_RETURN: u8 = 0x6a;
+const OP_CHECKSIG: u8 = 0xac;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotarisationError {
+    NoMessages,
+    // the messages are from more than one (symbol, height)
+    MixedRounds,
+    // no notary reported a bestk and bestmask
+    NoBestmask,
+    // a notary in bestmask has sent no message or no destutxo
+    MissingUtxo(u8),
+}
+
+impl fmt::Display for NotarisationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NotarisationError::NoMessages => write!(f, "no messages"),
+            NotarisationError::MixedRounds => write!(f, "messages from more than one round"),
+            NotarisationError::NoBestmask => write!(f, "no notary reported a bestmask"),
+            NotarisationError::MissingUtxo(senderind) => {
+                write!(f, "no destutxo from notary {} in bestmask", senderind)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NotarisationError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notarisation {
+    pub symbol: String,
+    pub height: u32,
+    // the bestk and bestmask most notaries reported
+    pub bestk: u8,
+    pub bestmask: u64,
+    pub srchash: [u8; 32],
+    pub unsigned: Transaction,
+    // None until every notary in bestmask has signed for the same bestk and bestmask
+    pub signed: Option<Transaction>,
+}
+
+impl Notarisation {
+    // the txid the notarisation will have on chain, once it is signed
+    pub fn txid(&self) -> Option<String> {
+        self.signed
+            .as_ref()
+            .map(|tx| hex::encode(tx.hash().reversed().take()))
+    }
+}
+
+pub fn tx_hex(tx: &Transaction) -> String {
+    hex::encode(serialize(tx).take())
+}
+
+// a script pushing data
+fn push(script: &mut Vec<u8>, data: &[u8]) {
+    let len = data.len();
+    if len < OP_PUSHDATA1 as usize {
+        script.push(len as u8);
+    } else if len <= u8::MAX as usize {
+        script.push(OP_PUSHDATA1);
+        script.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        script.push(OP_PUSHDATA2);
+        script.extend_from_slice(&(len as u16).to_le_bytes());
+    } else {
+        script.push(OP_PUSHDATA4);
+        script.extend_from_slice(&(len as u32).to_le_bytes());
+    }
+    script.extend_from_slice(data);
+}
+
+// dpow_rwopret for the dest chain; extras is anything iguana appends for the chain, eg MoM
+// data for assetchains, which isn't in the messages
+pub fn notarisation_opret(srchash: [u8; 32], height: u32, symbol: &str, extras: &[u8]) -> Vec<u8> {
+    let mut data = srchash.to_vec();
+    data.extend_from_slice(&height.to_le_bytes());
+    data.extend_from_slice(symbol.as_bytes());
+    data.push(0);
+    data.extend_from_slice(extras);
+
+    let mut script = vec![OP_RETURN];
+    push(&mut script, &data);
+    script
+}
+
+// builds the notarisation from all the messages of one round, using each notary's latest
+pub fn assemble_notarisation(
+    msgs: &[DpowNanoMsgHdr],
+    opret_extras: &[u8],
+) -> Result<Notarisation, NotarisationError> {
+    let first = msgs.first().ok_or(NotarisationError::NoMessages)?;
+    if msgs
+        .iter()
+        .any(|msg| msg.symbol != first.symbol || msg.height != first.height)
+    {
+        return Err(NotarisationError::MixedRounds);
+    }
+    let mut latest: BTreeMap<u8, &DpowNanoMsgHdr> = BTreeMap::new();
+    for msg in msgs {
+        latest.insert(msg.senderind, msg);
+    }
+
+    let mut votes: BTreeMap<(u8, u64), usize> = BTreeMap::new();
+    for msg in latest.values() {
+        let bestmask = mask_to_u64(&msg.notarize.bestmask);
+        if msg.notarize.bestk != BESTK_NONE && bestmask != 0 {
+            *votes.entry((msg.notarize.bestk, bestmask)).or_insert(0) += 1;
+        }
+    }
+    let ((bestk, bestmask), _) = votes
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .ok_or(NotarisationError::NoBestmask)?;
+    let agrees = |msg: &DpowNanoMsgHdr| {
+        msg.notarize.bestk == bestk && mask_to_u64(&msg.notarize.bestmask) == bestmask
+    };
+    let srchash = latest
+        .values()
+        .find(|msg| agrees(msg))
+        .map(|msg| msg.srchash)
+        .unwrap();
+
+    // inputs in notary order, as iguana has them
+    let mut inputs = vec![];
+    let mut sigs = vec![];
+    for senderind in (0..64u8).filter(|k| bestmask & (1 << k) != 0) {
+        let msg = latest
+            .get(&senderind)
+            .filter(|msg| msg.notarize.destutxo != [0; 32])
+            .ok_or(NotarisationError::MissingUtxo(senderind))?;
+        inputs.push(TransactionInput {
+            previous_output: OutPoint {
+                hash: H256::from(msg.notarize.destutxo),
+                index: msg.notarize.destvout as u32,
+            },
+            script_sig: Bytes::new(),
+            sequence: 0xffffffff,
+            ..Default::default()
+        });
+        let siglen = msg.notarize.siglens[0] as usize;
+        sigs.push((agrees(msg) && siglen > 0).then(|| &msg.notarize.sig1[..siglen.min(128)]));
+    }
+
+    let numinputs = inputs.len() as u64;
+    let value = (DPOW_UTXOSIZE * numinputs * 76 / 100)
+        .min((DPOW_UTXOSIZE * numinputs).saturating_sub(10000));
+    let mut p2pk = vec![];
+    push(&mut p2pk, &hex::decode(CRYPTO777_PUBKEY).unwrap());
+    p2pk.push(OP_CHECKSIG);
+    let symbol = first.symbol_str();
+    let unsigned = Transaction {
+        version: 1,
+        inputs,
+        outputs: vec![
+            TransactionOutput {
+                value,
+                script_pubkey: p2pk.into(),
+            },
+            TransactionOutput {
+                value: 0,
+                script_pubkey: notarisation_opret(srchash, first.height, &symbol, opret_extras)
+                    .into(),
+            },
+        ],
+        lock_time: 0,
+        ..Default::default()
+    };
+
+    // the inputs spend P2PK outputs, so each scriptSig is just the signature
+    let signed = sigs.iter().all(Option::is_some).then(|| {
+        let mut signed = unsigned.clone();
+        for (input, sig) in signed.inputs.iter_mut().zip(sigs.iter()) {
+            let mut script_sig = vec![];
+            push(&mut script_sig, sig.unwrap());
+            input.script_sig = script_sig.into();
+        }
+        signed
+    });
+
+    Ok(Notarisation {
+        symbol,
+        height: first.height,
+        bestk,
+        bestmask,
+        srchash,
+        unsigned,
+        signed,
+    })
+}
@@ -0,0 +1,184 @@
+use crate::{
+    packethash_pow, produce_packethash_signature, validate_packet_signature, validate_packethash,
+    DpowNanoMsgHdr, IguanaPacketHeader,
+};
+use bincode::Options;
+use secp256k1::{PublicKey, SecretKey};
+use std::fmt;
+
+pub const HEADER_SIZE: usize = 104;
+// iguana only puts small things like notarisation txs in the data after the DpowNanoMsgHdr
+pub const DEFAULT_MAX_DATALEN: usize = 65536;
+
+// the C struct has a trailing padding byte that is not sent over the wire
+pub fn dpow_msg_size() -> usize {
+    std::mem::size_of::<DpowNanoMsgHdr>() - 1
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PacketError {
+    // fewer bytes than the header or packetlen claims
+    Truncated,
+    BadPacketHash,
+    BadSignature,
+    // payload too short for a DpowNanoMsgHdr plus its datalen
+    BadPayload,
+    // senderind is not in the listener's notary set
+    UnknownSender,
+    // signed by a key other than the one known for senderind
+    PubkeyMismatch,
+    // the sender is over its rate limit, see ratelimit.rs
+    RateLimited,
+    // packetlen is over the decoder's max_packet_size
+    PacketTooLarge,
+    // datalen is over the decoder's max_datalen
+    DatalenTooLarge,
+}
+
+impl PacketError {
+    // short label, used as a metrics label value
+    pub fn reason(&self) -> &'static str {
+        match self {
+            PacketError::Truncated => "truncated",
+            PacketError::BadPacketHash => "bad_packethash",
+            PacketError::BadSignature => "bad_signature",
+            PacketError::BadPayload => "bad_payload",
+            PacketError::UnknownSender => "unknown_sender",
+            PacketError::PubkeyMismatch => "pubkey_mismatch",
+            PacketError::RateLimited => "rate_limited",
+            PacketError::PacketTooLarge => "packet_too_large",
+            PacketError::DatalenTooLarge => "datalen_too_large",
+        }
+    }
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason())
+    }
+}
+
+impl std::error::Error for PacketError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Packet {
+    pub header: IguanaPacketHeader,
+    pub pubkey: PublicKey,
+    pub dpow_msg: DpowNanoMsgHdr,
+    // datalen bytes following the DpowNanoMsgHdr
+    pub extra: Vec<u8>,
+}
+
+// bounds on what the decoder accepts, checked before anything is hashed or copied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecodeLimits {
+    // header plus payload
+    pub max_packet_size: usize,
+    pub max_datalen: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_packet_size: HEADER_SIZE + dpow_msg_size() + DEFAULT_MAX_DATALEN,
+            max_datalen: DEFAULT_MAX_DATALEN,
+        }
+    }
+}
+
+// decode and validate the first packet in buffer, returning it and any bytes after it
+pub fn decode_packet(buffer: &[u8]) -> Result<(Packet, &[u8]), PacketError> {
+    decode_packet_with_limits(buffer, &DecodeLimits::default())
+}
+
+pub fn decode_packet_with_limits<'a>(
+    buffer: &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<(Packet, &'a [u8]), PacketError> {
+    let (header, payload, rest) = split_packet_with_limits(buffer, limits)?;
+    Ok((verify_packet(header, payload, limits)?, rest))
+}
+
+// the first packet's header and payload and any bytes after it, only checking its length
+// and packethash, so duplicates can be dropped before the signature is recovered
+pub fn split_packet_with_limits<'a>(
+    buffer: &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<(IguanaPacketHeader, &'a [u8], &'a [u8]), PacketError> {
+    let binconf = bincode::DefaultOptions::new().with_fixint_encoding();
+
+    if buffer.len() < HEADER_SIZE {
+        return Err(PacketError::Truncated);
+    }
+    let header: IguanaPacketHeader = binconf
+        .deserialize(&buffer[..HEADER_SIZE])
+        .map_err(|_| PacketError::Truncated)?;
+
+    let packet_end = HEADER_SIZE + header.packetlen as usize;
+    if packet_end > limits.max_packet_size {
+        return Err(PacketError::PacketTooLarge);
+    }
+    if buffer.len() < packet_end {
+        return Err(PacketError::Truncated);
+    }
+    let payload = &buffer[HEADER_SIZE..packet_end];
+
+    validate_packethash(&header, &payload.to_vec()).map_err(|_| PacketError::BadPacketHash)?;
+    Ok((header, payload, &buffer[packet_end..]))
+}
+
+// recovers the signer of a packet from split_packet_with_limits and decodes its payload
+pub fn verify_packet(
+    header: IguanaPacketHeader,
+    payload: &[u8],
+    limits: &DecodeLimits,
+) -> Result<Packet, PacketError> {
+    let binconf = bincode::DefaultOptions::new().with_fixint_encoding();
+
+    let pubkey = validate_packet_signature(&header).map_err(|_| PacketError::BadSignature)?;
+    // TODO: add "validate_pubkey" flag
+
+    let msg_size = dpow_msg_size();
+    if payload.len() < msg_size {
+        return Err(PacketError::BadPayload);
+    }
+    let dpow_msg: DpowNanoMsgHdr = binconf
+        .deserialize(&payload[..msg_size])
+        .map_err(|_| PacketError::BadPayload)?;
+
+    if dpow_msg.datalen as usize > limits.max_datalen {
+        return Err(PacketError::DatalenTooLarge);
+    }
+    let extra = payload[msg_size..]
+        .get(..dpow_msg.datalen as usize)
+        .ok_or(PacketError::BadPayload)?
+        .to_vec();
+
+    Ok(Packet {
+        header,
+        pubkey,
+        dpow_msg,
+        extra,
+    })
+}
+
+// header + DpowNanoMsgHdr + extra, signed the way iguana does it
+// datalen is set from extra; None only if the packethash PoW gives up
+pub fn encode_packet(dpow_msg: &DpowNanoMsgHdr, extra: &[u8], sk: &SecretKey) -> Option<Vec<u8>> {
+    let binconf = bincode::DefaultOptions::new().with_fixint_encoding();
+    let mut dpow_msg = dpow_msg.clone();
+    dpow_msg.datalen = extra.len() as u32;
+    let mut payload = binconf.serialize(&dpow_msg).ok()?;
+    payload.extend_from_slice(extra);
+
+    let (nonce, packethash) = packethash_pow(&payload).ok()?;
+    let header = IguanaPacketHeader {
+        sig: produce_packethash_signature(packethash, sk).ok()?,
+        packethash,
+        nonce,
+        packetlen: payload.len() as u32,
+    };
+    let mut packet = binconf.serialize(&header).ok()?;
+    packet.extend(payload);
+    Some(packet)
+}
@@ -0,0 +1,84 @@
+use crate::db::{get_notaries, get_round_notaries_between, get_rounds_between};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+// how a notary took part in the rounds of one symbol, or of all of them
+#[derive(Clone, Debug, Default, Serialize, PartialEq)]
+pub struct Participation {
+    pub rounds: u32,
+    pub in_bestmask: u32,
+    // rounds the notary sent no message in
+    pub absent: u32,
+    // percentage of rounds the notary was in the agreed bestmask of
+    pub bestmask_pct: f64,
+    // average seconds from the first message of a round to the notary's first
+    pub avg_first_message_secs: Option<f64>,
+    #[serde(skip)]
+    first_message_total: u64,
+}
+
+impl Participation {
+    // joined_after is None if the notary was absent from the round
+    pub fn add(&mut self, in_bestmask: bool, joined_after: Option<u32>) {
+        self.rounds += 1;
+        self.in_bestmask += in_bestmask as u32;
+        match joined_after {
+            Some(secs) => self.first_message_total += secs as u64,
+            None => self.absent += 1,
+        }
+        self.bestmask_pct = self.in_bestmask as f64 * 100.0 / self.rounds as f64;
+        let present = self.rounds - self.absent;
+        self.avg_first_message_secs =
+            (present > 0).then(|| self.first_message_total as f64 / present as f64);
+    }
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct NotaryParticipation {
+    pub name: String,
+    pub total: Participation,
+    pub symbols: BTreeMap<String, Participation>,
+}
+
+// participation of every notary in the rounds started within [from, to)
+pub fn notary_participation(conn: &Connection, from: u32, to: u32) -> Vec<NotaryParticipation> {
+    let rounds = get_rounds_between(conn, from, to);
+    let mut joined: HashMap<(String, u32), HashMap<u8, u32>> = HashMap::new();
+    for (symbol, height, notary_id, time) in get_round_notaries_between(conn, from, to) {
+        joined
+            .entry((symbol, height))
+            .or_default()
+            .insert(notary_id, time);
+    }
+
+    get_notaries(conn)
+        .into_iter()
+        .map(|notary| {
+            let mut stats = NotaryParticipation {
+                name: notary.name,
+                total: Participation::default(),
+                symbols: BTreeMap::new(),
+            };
+            for round in rounds.iter() {
+                let bestmask = round
+                    .bestmask
+                    .as_ref()
+                    .and_then(|mask| u64::from_str_radix(mask, 16).ok())
+                    .unwrap_or(0);
+                let in_bestmask = bestmask & (1 << notary.id) != 0;
+                let joined_after = joined
+                    .get(&(round.symbol.clone(), round.height))
+                    .and_then(|notaries| notaries.get(&notary.id))
+                    .map(|time| time.saturating_sub(round.started));
+                stats.total.add(in_bestmask, joined_after);
+                stats
+                    .symbols
+                    .entry(round.symbol.clone())
+                    .or_default()
+                    .add(in_bestmask, joined_after);
+            }
+            stats
+        })
+        .collect()
+}
@@ -0,0 +1,126 @@
+use crate::storage::Storage;
+use crate::transport::Transport;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::net::Ipv4Addr;
+use std::sync::mpsc::Sender;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PeerCommand {
+    Add(String),
+    Remove(String),
+    List,
+    Ban(String),
+    Unban(String),
+}
+
+// peer commands are executed by the thread owning the bus socket
+// the new peer list, or an error message, is sent back on reply
+pub struct PeerRequest {
+    pub command: PeerCommand,
+    pub reply: Sender<Result<PeerList, String>>,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct PeerList {
+    pub connected: Vec<String>,
+    pub banned: Vec<String>,
+}
+
+pub fn validate_ip(ip: &str) -> Result<String, String> {
+    ip.parse::<Ipv4Addr>()
+        .map(|ip| ip.to_string())
+        .map_err(|_| format!("invalid IPv4 address '{}'", ip))
+}
+
+// tracks the outgoing connections of the bus transport so they can be shut down later
+pub struct Peers {
+    port: String,
+    connected: BTreeSet<String>,
+    banned: BTreeSet<String>,
+}
+
+impl Peers {
+    // bans are persisted in storage so they survive restarts
+    pub fn new(storage: &dyn Storage, port: &str) -> Self {
+        Peers {
+            port: port.to_string(),
+            connected: BTreeSet::new(),
+            banned: storage.banned_ips().into_iter().collect(),
+        }
+    }
+
+    pub fn is_connected(&self, ip: &str) -> bool {
+        self.connected.contains(ip)
+    }
+
+    pub fn connected_count(&self) -> usize {
+        self.connected.len()
+    }
+
+    pub fn is_banned(&self, ip: &str) -> bool {
+        self.banned.contains(ip)
+    }
+
+    fn addr(&self, ip: &str) -> String {
+        format!("tcp://{}:{}", ip, self.port)
+    }
+
+    pub fn connect(&mut self, transport: &mut dyn Transport, ip: &str) -> Result<(), String> {
+        if self.is_banned(ip) {
+            return Err(format!("{} is banned", ip));
+        }
+        if self.is_connected(ip) {
+            return Ok(());
+        }
+        let dial = self.addr(ip);
+        transport
+            .connect(&dial)
+            .map_err(|err| format!("failed connect to {}: {}", dial, err))?;
+        self.connected.insert(ip.to_string());
+        Ok(())
+    }
+
+    pub fn disconnect(&mut self, transport: &mut dyn Transport, ip: &str) -> Result<(), String> {
+        if !self.connected.remove(ip) {
+            return Err(format!("not connected to {}", ip));
+        }
+        transport
+            .disconnect(&self.addr(ip))
+            .map_err(|err| format!("failed to disconnect from {}: {}", ip, err))
+    }
+
+    pub fn ban(&mut self, transport: &mut dyn Transport, storage: &dyn Storage, ip: &str) {
+        storage.ban_ip(ip);
+        self.banned.insert(ip.to_string());
+        let _ = self.disconnect(transport, ip);
+    }
+
+    pub fn unban(&mut self, storage: &dyn Storage, ip: &str) {
+        storage.unban_ip(ip);
+        self.banned.remove(ip);
+    }
+
+    pub fn list(&self) -> PeerList {
+        PeerList {
+            connected: self.connected.iter().cloned().collect(),
+            banned: self.banned.iter().cloned().collect(),
+        }
+    }
+
+    pub fn handle(
+        &mut self,
+        transport: &mut dyn Transport,
+        storage: &dyn Storage,
+        command: PeerCommand,
+    ) -> Result<PeerList, String> {
+        match command {
+            PeerCommand::Add(ip) => self.connect(transport, &validate_ip(&ip)?)?,
+            PeerCommand::Remove(ip) => self.disconnect(transport, &validate_ip(&ip)?)?,
+            PeerCommand::List => {}
+            PeerCommand::Ban(ip) => self.ban(transport, storage, &validate_ip(&ip)?),
+            PeerCommand::Unban(ip) => self.unban(storage, &validate_ip(&ip)?),
+        }
+        Ok(self.list())
+    }
+}
@@ -0,0 +1,277 @@
+use crate::bestk::check_bestk;
+use crate::broadcast::Broadcaster;
+use crate::capture::{now_micros, CaptureWriter};
+use crate::dedup::SeenPackets;
+use crate::message::DecodedMessage;
+use crate::metrics::Metrics;
+use crate::notaries::NotarySet;
+use crate::packet::{split_packet_with_limits, verify_packet, DecodeLimits, Packet, PacketError};
+use crate::peers::{PeerRequest, Peers};
+use crate::ratelimit::RateLimiter;
+use crate::rounds::{format_mask, RoundChange, Rounds};
+use crate::storage::Storage;
+use crate::subscriptions::Subscriptions;
+use crate::transport::Transport;
+use crate::utxos::UtxoTracker;
+use crate::DPOW_MINSIGS;
+use log::{debug, error, info, warn};
+use rusqlite::Connection;
+use std::io::{self, ErrorKind};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// callbacks for code embedding the listener, run after the built in processing
+pub trait MessageHandler: Send {
+    // a packet that passed validation, received at `received` (unix seconds)
+    fn on_message(&self, packet: &Packet, received: u32);
+
+    // a packet that failed validation
+    fn on_invalid(&self, _err: PacketError) {}
+}
+
+// validation, decoding and storage shared by the live listener and capture replay
+pub struct Pipeline<S: Storage = Connection> {
+    pub storage: S,
+    pub notaries: NotarySet,
+    pub limits: DecodeLimits,
+    pub rounds: Arc<Mutex<Rounds>>,
+    pub subscriptions: Subscriptions,
+    pub metrics: Arc<Metrics>,
+    // the same packet arrives from every peer, only the first copy is processed
+    pub seen: Mutex<SeenPackets>,
+    // spam detection, off unless set
+    pub rate_limiter: Option<Mutex<RateLimiter>>,
+    // UTXOs offered per round, to spot notaries reusing or running out of them
+    pub utxos: Mutex<UtxoTracker>,
+    handlers: Vec<Box<dyn MessageHandler>>,
+}
+
+impl<S: Storage> Pipeline<S> {
+    pub fn new(
+        storage: S,
+        rounds: Arc<Mutex<Rounds>>,
+        subscriptions: Subscriptions,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Pipeline {
+            storage,
+            notaries: NotarySet::default(),
+            limits: DecodeLimits::default(),
+            rounds,
+            subscriptions,
+            metrics,
+            seen: Mutex::new(SeenPackets::default()),
+            rate_limiter: None,
+            utxos: Mutex::new(UtxoTracker::default()),
+            handlers: vec![],
+        }
+    }
+
+    pub fn add_handler(&mut self, handler: Box<dyn MessageHandler>) {
+        self.handlers.push(handler);
+    }
+
+    fn invalid(&self, err: PacketError) {
+        self.metrics.packet_invalid(err);
+        warn!(reason = err.reason(); "dropping invalid packet: {}", err);
+        for handler in self.handlers.iter() {
+            handler.on_invalid(err);
+        }
+    }
+
+    // processes every packet in one raw nanomsg message received at `received` (unix seconds)
+    // returns the IPs seen in ipbits for the first time, for the caller to connect to
+    pub fn process(&self, data: &[u8], received: u32) -> Vec<String> {
+        let mut new_ips = vec![];
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            self.metrics.packet_received();
+            let (header, payload) = match split_packet_with_limits(remaining, &self.limits) {
+                Ok((header, payload, rest)) => {
+                    remaining = rest;
+                    (header, payload)
+                }
+                // without a valid packetlen the rest of the message can't be split up
+                Err(err) => {
+                    self.invalid(err);
+                    break;
+                }
+            };
+            // duplicates are dropped before recovering the signature, but only packets that
+            // pass it are remembered, so a forged copy can't hide the real one
+            let packethash = header.packethash;
+            if self.seen.lock().unwrap().contains_at(&packethash, received) {
+                self.metrics.packet_duplicate();
+                continue;
+            }
+            let packet = match verify_packet(header, payload, &self.limits) {
+                Ok(packet) => packet,
+                Err(err) => {
+                    self.invalid(err);
+                    continue;
+                }
+            };
+            if let Err(err) = self.notaries.check(&packet) {
+                self.invalid(err);
+                continue;
+            }
+            if !self.seen.lock().unwrap().insert_at(packethash, received) {
+                self.metrics.packet_duplicate();
+                continue;
+            }
+            if let Some(rate_limiter) = &self.rate_limiter {
+                let check = rate_limiter.lock().unwrap().check(
+                    packet.dpow_msg.senderind,
+                    &packet.pubkey,
+                    received,
+                );
+                for incident in check.incidents.iter() {
+                    warn!(senderind = incident.senderind, pubkey = incident.pubkey.as_str(), kind = incident.kind.as_str();
+                        "sender over rate limit, {} messages in {}s", incident.count, incident.window_secs);
+                    self.storage.record_incident(incident);
+                }
+                if check.drop {
+                    self.invalid(PacketError::RateLimited);
+                    continue;
+                }
+            }
+            self.metrics.packet_valid(packet.header.nonce);
+            let dpow_msg = &packet.dpow_msg;
+
+            let db_write_start = Instant::now();
+            self.storage.update_lastseen(dpow_msg.senderind, received);
+            self.storage
+                .update_ip_logs(dpow_msg.senderind, dpow_msg.myipbits, received);
+            new_ips.extend(self.storage.update_known_ips(
+                dpow_msg.senderind,
+                dpow_msg.ipbits.to_vec(),
+                received,
+            ));
+            let symbol = dpow_msg.symbol_str();
+            if !symbol.is_empty() {
+                self.storage.update_notary_height(
+                    &symbol,
+                    dpow_msg.senderind,
+                    dpow_msg.height,
+                    dpow_msg.srchash,
+                    received,
+                );
+            }
+            self.metrics.db_write(db_write_start.elapsed());
+
+            let mut decoded = DecodedMessage::new(dpow_msg);
+            decoded.received = received;
+            decoded.log();
+            let numnotaries = self.notaries.len();
+            let adopted = self
+                .rounds
+                .lock()
+                .unwrap()
+                .get_height(&symbol, dpow_msg.height)
+                .and_then(|round| round.consensus_of_others(dpow_msg.senderind))
+                .map(|(bestk, bestmask, _)| (bestk, bestmask));
+            if let Err(mismatch) = check_bestk(
+                dpow_msg,
+                numnotaries,
+                DPOW_MINSIGS.min(numnotaries),
+                adopted,
+            ) {
+                self.metrics.bestk_mismatch(&decoded.sender);
+                debug!(sender = decoded.sender.as_str();
+                    "reported bestk {} bestmask {}, expected bestk {} bestmask {}",
+                    mismatch.reported.0 as i8, format_mask(mismatch.reported.1),
+                    mismatch.expected.0 as i8, format_mask(mismatch.expected.1));
+            }
+            self.metrics.message(&decoded.sender, &decoded.symbol);
+            let round_changes = self.rounds.lock().unwrap().update_at(dpow_msg, received);
+            for change in round_changes.iter() {
+                if let RoundChange::Fork {
+                    fork,
+                    new_hash: true,
+                } = change
+                {
+                    let sides: Vec<String> = fork
+                        .sides
+                        .iter()
+                        .map(|side| format!("{} {}", side.hash, side.notaries.join(",")))
+                        .collect();
+                    warn!(symbol = fork.symbol.as_str(), height = fork.height, kind = fork.kind.as_str();
+                        "notaries disagree on {}: {}", fork.kind, sides.join(" vs "));
+                }
+                self.storage.save_round_change(change);
+            }
+            for utxo in self.utxos.lock().unwrap().update_at(dpow_msg, received) {
+                match (&utxo.txid, &utxo.previous) {
+                    (None, _) => {
+                        warn!(symbol = utxo.symbol.as_str(), height = utxo.height, sender = decoded.sender.as_str();
+                            "notary offered no {} UTXO, it may have run out of split UTXOs", utxo.kind)
+                    }
+                    (Some(txid), Some(previous)) => {
+                        warn!(symbol = utxo.symbol.as_str(), height = utxo.height, sender = decoded.sender.as_str();
+                            "{} UTXO {}/{} was already offered by notary {} for {} {}",
+                            utxo.kind, txid, utxo.vout.unwrap(), previous.senderind, previous.symbol, previous.height)
+                    }
+                    _ => {}
+                }
+                self.storage.save_utxo(&utxo);
+            }
+            if !self.subscriptions.is_empty() {
+                self.subscriptions.publish(&decoded);
+            }
+            for handler in self.handlers.iter() {
+                handler.on_message(&packet, received);
+            }
+        }
+        new_ips
+    }
+
+    // reads messages from transport until it ends, connecting to newly learned IPs and
+    // applying peer commands and our own broadcasts between messages; the transport's
+    // receive timeout bounds how long either may wait
+    pub fn run(
+        &self,
+        transport: &mut dyn Transport,
+        peers: &mut Peers,
+        peer_requests: &Receiver<PeerRequest>,
+        mut capture: Option<&mut CaptureWriter>,
+        mut broadcaster: Option<&mut Broadcaster>,
+    ) -> io::Result<()> {
+        loop {
+            while let Ok(request) = peer_requests.try_recv() {
+                let result = peers.handle(transport, &self.storage, request.command);
+                let _ = request.reply.send(result);
+                self.metrics.set_connected_peers(peers.connected_count());
+            }
+            if let Some(broadcaster) = broadcaster.as_mut() {
+                if let Err(err) = broadcaster.poll(transport, &peers.list().connected) {
+                    error!("failed to broadcast our message: {}", err);
+                }
+            }
+
+            let buffer = match transport.recv() {
+                Ok(Some(buffer)) => buffer,
+                Ok(None) => continue,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            };
+            let received = transport.last_received().unwrap_or_else(now_micros);
+            if let Some(capture) = capture.as_mut() {
+                if let Err(err) = capture.write(received, &buffer) {
+                    error!("failed to write capture file: {}", err);
+                }
+            }
+            for ip in self.process(&buffer, (received / 1_000_000) as u32) {
+                connect_to_ip(peers, transport, &ip);
+            }
+            self.metrics.set_connected_peers(peers.connected_count());
+        }
+    }
+}
+
+pub fn connect_to_ip(peers: &mut Peers, transport: &mut dyn Transport, ip: &str) {
+    match peers.connect(transport, ip) {
+        Ok(_) => info!(ip; "connect to {}", ip),
+        Err(err) => warn!(ip; "{}", err),
+    };
+}
@@ -0,0 +1,143 @@
+use crate::config::RateLimitConfig;
+use crate::db::SpamIncident;
+use secp256k1::PublicKey;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+// windows tracked per map, the oldest is forgotten beyond this so a flood of new keys
+// can't grow the maps without bound
+const MAX_TRACKED: usize = 4096;
+
+// messages counted in fixed windows of window_secs
+#[derive(Clone, Copy, Debug)]
+struct Window {
+    start: u32,
+    count: u32,
+}
+
+// the current window of each key, expiring the oldest first like SeenPackets
+struct Windows<K> {
+    windows: HashMap<K, Window>,
+    // keys in the order their windows started, with the start
+    order: VecDeque<(K, u32)>,
+}
+
+impl<K: Eq + Hash + Clone> Windows<K> {
+    fn new() -> Self {
+        Windows {
+            windows: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    // drops the front of order, and its window unless that has since restarted
+    fn pop_oldest(&mut self) -> bool {
+        match self.order.pop_front() {
+            Some((key, start)) => {
+                if self.windows.get(&key).map(|window| window.start) == Some(start) {
+                    self.windows.remove(&key);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    // counts a message at `now`, returning the count in the current window
+    fn hit(&mut self, key: K, now: u32, window_secs: u32) -> u32 {
+        while let Some((_, start)) = self.order.front() {
+            if now.saturating_sub(*start) < window_secs {
+                break;
+            }
+            self.pop_oldest();
+        }
+        if let Some(window) = self.windows.get_mut(&key) {
+            if now.saturating_sub(window.start) < window_secs {
+                window.count = window.count.saturating_add(1);
+                return window.count;
+            }
+        }
+        self.windows.insert(
+            key.clone(),
+            Window {
+                start: now,
+                count: 1,
+            },
+        );
+        self.order.push_back((key, now));
+        while self.windows.len() > MAX_TRACKED && self.pop_oldest() {}
+        1
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RateCheck {
+    // senders that went over a limit with this message, once per window
+    pub incidents: Vec<SpamIncident>,
+    // the senderind or pubkey is over its limit
+    pub over_limit: bool,
+    // over_limit and configured to drop
+    pub drop: bool,
+}
+
+// tracks message rates per senderind and per recovered pubkey, as a notary could also
+// spam under several senderinds with the one key
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    senders: Windows<u8>,
+    pubkeys: Windows<PublicKey>,
+}
+
+impl RateLimiter {
+    // a zero-length window would never expire anything
+    pub fn new(config: &RateLimitConfig) -> Result<Self, String> {
+        if config.window_secs == 0 {
+            return Err("rate_limit window_secs must be above 0".to_string());
+        }
+        Ok(RateLimiter {
+            config: config.clone(),
+            senders: Windows::new(),
+            pubkeys: Windows::new(),
+        })
+    }
+
+    // counts a valid message received at `now` (unix seconds)
+    pub fn check(&mut self, senderind: u8, pubkey: &PublicKey, now: u32) -> RateCheck {
+        let window_secs = self.config.window_secs;
+        let pubkey_hex = hex::encode(pubkey.serialize());
+        let mut check = RateCheck::default();
+
+        let count = self.senders.hit(senderind, now, window_secs);
+        if count > self.config.max_per_sender {
+            check.over_limit = true;
+        }
+        if self.config.max_per_sender.checked_add(1) == Some(count) {
+            check.incidents.push(SpamIncident {
+                senderind,
+                pubkey: pubkey_hex.clone(),
+                kind: "senderind".to_string(),
+                count,
+                window_secs,
+                time: now,
+            });
+        }
+
+        let count = self.pubkeys.hit(*pubkey, now, window_secs);
+        if count > self.config.max_per_pubkey {
+            check.over_limit = true;
+        }
+        if self.config.max_per_pubkey.checked_add(1) == Some(count) {
+            check.incidents.push(SpamIncident {
+                senderind,
+                pubkey: pubkey_hex,
+                kind: "pubkey".to_string(),
+                count,
+                window_secs,
+                time: now,
+            });
+        }
+
+        check.drop = check.over_limit && self.config.drop;
+        check
+    }
+}
@@ -0,0 +1,109 @@
+use crate::dedup::{SeenPackets, DEFAULT_SEEN_MAX_AGE};
+use crate::notaries::NotarySet;
+use crate::packet::{split_packet_with_limits, verify_packet, DecodeLimits};
+use crate::transport::Transport;
+use log::{debug, warn};
+use std::io::{self, ErrorKind};
+
+// packethashes remembered to stop a packet being forwarded again when it comes back
+pub const RELAY_SEEN_CAPACITY: usize = 65536;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RelayStats {
+    pub forwarded: u64,
+    pub invalid: u64,
+    pub duplicate: u64,
+}
+
+// forwards packets between two bus networks, eg a socket bound for our notaries and one
+// connected to the public peers, dropping any that fail validation or were already sent
+pub struct Relay {
+    sides: [Box<dyn Transport>; 2],
+    pub limits: DecodeLimits,
+    notaries: NotarySet,
+    seen: SeenPackets,
+    stats: RelayStats,
+}
+
+impl Relay {
+    // every notary needs a pubkey, as without one any signature would be forwarded
+    pub fn new(
+        a: Box<dyn Transport>,
+        b: Box<dyn Transport>,
+        notaries: NotarySet,
+    ) -> Result<Self, String> {
+        let without_pubkey = notaries.without_pubkey();
+        if !without_pubkey.is_empty() {
+            return Err(format!(
+                "relaying needs a pubkey for every notary, missing for {}",
+                without_pubkey.join(", ")
+            ));
+        }
+        Ok(Relay {
+            sides: [a, b],
+            limits: DecodeLimits::default(),
+            notaries,
+            seen: SeenPackets::new(RELAY_SEEN_CAPACITY).with_max_age(DEFAULT_SEEN_MAX_AGE),
+            stats: RelayStats::default(),
+        })
+    }
+
+    pub fn stats(&self) -> RelayStats {
+        self.stats
+    }
+
+    // the valid, unseen packets in one message, each re-encoded as its own message
+    pub fn filter(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut forward = vec![];
+        let mut remaining = data;
+        while !remaining.is_empty() {
+            let (header, payload, rest) = match split_packet_with_limits(remaining, &self.limits) {
+                Ok(split) => split,
+                Err(err) => {
+                    self.stats.invalid += 1;
+                    debug!(reason = err.reason(); "not relaying invalid packet: {}", err);
+                    break;
+                }
+            };
+            let bytes = &remaining[..remaining.len() - rest.len()];
+            remaining = rest;
+            // as in the pipeline, only packets with a valid signature are remembered
+            let packethash = header.packethash;
+            if self.seen.contains(&packethash) {
+                self.stats.duplicate += 1;
+                continue;
+            }
+            let checked = verify_packet(header, payload, &self.limits)
+                .and_then(|packet| self.notaries.check(&packet));
+            if let Err(err) = checked {
+                self.stats.invalid += 1;
+                debug!(reason = err.reason(); "not relaying invalid packet: {}", err);
+                continue;
+            }
+            self.seen.insert(packethash);
+            self.stats.forwarded += 1;
+            forward.push(bytes.to_vec());
+        }
+        forward
+    }
+
+    // receives from each side in turn, so the transports' receive timeouts should be short
+    // returns Ok once either side has ended
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            for from in 0..2 {
+                let data = match self.sides[from].recv() {
+                    Ok(Some(data)) => data,
+                    Ok(None) => continue,
+                    Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+                    Err(err) => return Err(err),
+                };
+                for packet in self.filter(&data) {
+                    if let Err(err) = self.sides[1 - from].send(&packet) {
+                        warn!("failed to relay packet: {}", err);
+                    }
+                }
+            }
+        }
+    }
+}
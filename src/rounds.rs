@@ -0,0 +1,461 @@
+use crate::message::notary_name;
+use crate::{mask_to_u64, now_sec, DpowNanoMsgHdr, DPOW_MINSIGS, FIRST_PARTY};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+
+// bestk is an int8_t in iguana; -1 (255 on the wire) means no bestk chosen yet
+pub const BESTK_NONE: u8 = 255;
+// heights kept per symbol, so late messages and notaries on an older height are still
+// counted in their own round
+pub const DEFAULT_ROUND_WINDOW: usize = 16;
+// symbols tracked at once; iguana notarises a few dozen chains, anything past this is noise
+pub const DEFAULT_MAX_SYMBOLS: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotaryRound {
+    pub bestk: u8,
+    pub bestmask: u64,
+    pub recvmask: u64,
+    pub lastseen: u32,
+    // first message from this notary for the round
+    pub joined: u32,
+    pub srchash: [u8; 32],
+    pub desthash: [u8; 32],
+}
+
+// dPoW round for a single (symbol, height)
+#[derive(Clone, Debug, PartialEq)]
+pub struct SymbolRound {
+    pub symbol: String,
+    pub height: u32,
+    pub srchash: [u8; 32],
+    pub desthash: [u8; 32],
+    pub notaries: BTreeMap<u8, NotaryRound>,
+    pub updated: u32,
+    // first message for this height
+    pub started: u32,
+    // when every notary in the consensus bestmask agreed on it
+    pub converged: Option<u32>,
+    // when a higher height of the symbol first appeared
+    pub ended: Option<u32>,
+}
+
+impl SymbolRound {
+    // the (bestk, bestmask) pair reported by the most notaries, with its vote count
+    pub fn consensus(&self) -> Option<(u8, u64, usize)> {
+        self.consensus_without(None)
+    }
+
+    // the consensus of every notary but senderind, which it adopts in dpow_bestconsensus
+    pub fn consensus_of_others(&self, senderind: u8) -> Option<(u8, u64, usize)> {
+        self.consensus_without(Some(senderind))
+    }
+
+    fn consensus_without(&self, skip: Option<u8>) -> Option<(u8, u64, usize)> {
+        let mut votes: HashMap<(u8, u64), usize> = HashMap::new();
+        for (senderind, notary) in self.notaries.iter() {
+            if Some(*senderind) == skip || notary.bestk == BESTK_NONE || notary.bestmask == 0 {
+                continue;
+            }
+            *votes.entry((notary.bestk, notary.bestmask)).or_insert(0) += 1;
+        }
+        votes
+            .into_iter()
+            .max_by_key(|((bestk, bestmask), count)| (*count, *bestmask, *bestk))
+            .map(|((bestk, bestmask), count)| (bestk, bestmask, count))
+    }
+
+    // the consensus bestmask has the required signers and all of them report it
+    fn is_converged(&self, required_sigs: usize) -> bool {
+        let (bestk, bestmask, _) = match self.consensus() {
+            Some(consensus) => consensus,
+            None => return false,
+        };
+        if (bestmask.count_ones() as usize) < required_sigs {
+            return false;
+        }
+        (0..64)
+            .filter(|i| bestmask & (1 << i) != 0)
+            .all(|i| match self.notaries.get(&i) {
+                Some(notary) => notary.bestk == bestk && notary.bestmask == bestmask,
+                None => false,
+            })
+    }
+
+    // notaries grouped by the srchash or desthash they reported, largest group first
+    // all zero hashes are left out as they mean the notary doesn't have one yet
+    pub fn sides(&self, kind: HashKind) -> Vec<ForkSide> {
+        let mut sides: BTreeMap<[u8; 32], Vec<String>> = BTreeMap::new();
+        for (senderind, notary) in self.notaries.iter() {
+            let hash = match kind {
+                HashKind::Src => notary.srchash,
+                HashKind::Dest => notary.desthash,
+            };
+            if hash != [0; 32] {
+                sides.entry(hash).or_default().push(notary_name(*senderind));
+            }
+        }
+        let mut sides: Vec<ForkSide> = sides
+            .into_iter()
+            .map(|(hash, notaries)| ForkSide {
+                hash: hex::encode(hash),
+                notaries,
+            })
+            .collect();
+        sides.sort_by_key(|side| Reverse(side.notaries.len()));
+        sides
+    }
+
+    fn new(dpow_msg: &DpowNanoMsgHdr, symbol: String, now: u32, ended: Option<u32>) -> Self {
+        SymbolRound {
+            symbol,
+            height: dpow_msg.height,
+            srchash: dpow_msg.srchash,
+            desthash: dpow_msg.desthash,
+            notaries: BTreeMap::new(),
+            updated: now,
+            started: now,
+            converged: None,
+            ended,
+        }
+    }
+
+    // the round as stored in the db
+    pub fn record(&self) -> RoundRecord {
+        let consensus = self.consensus();
+        RoundRecord {
+            symbol: self.symbol.clone(),
+            height: self.height,
+            started: self.started,
+            converged: self.converged,
+            ended: self.ended,
+            bestk: consensus.map(|(bestk, _, _)| bestk),
+            bestmask: consensus.map(|(_, bestmask, _)| format_mask(bestmask)),
+            notaries: self.notaries.len(),
+        }
+    }
+
+    pub fn summary(&self) -> RoundSummary {
+        let consensus = self.consensus();
+        RoundSummary {
+            symbol: self.symbol.clone(),
+            height: self.height,
+            srchash: hex::encode(self.srchash),
+            desthash: hex::encode(self.desthash),
+            consensus_bestk: consensus.map(|(bestk, _, _)| bestk),
+            consensus_bestmask: consensus.map(|(_, bestmask, _)| format_mask(bestmask)),
+            consensus_votes: consensus.map_or(0, |(_, _, count)| count),
+            updated: self.updated,
+            started: self.started,
+            converged: self.converged,
+            notaries: self
+                .notaries
+                .iter()
+                .map(|(senderind, notary)| NotaryRoundSummary {
+                    index: *senderind,
+                    name: FIRST_PARTY[*senderind as usize].to_string(),
+                    bestk: (notary.bestk != BESTK_NONE).then_some(notary.bestk),
+                    bestmask: format_mask(notary.bestmask),
+                    recvmask: format_mask(notary.recvmask),
+                    lastseen: notary.lastseen,
+                    joined: notary.joined,
+                })
+                .collect(),
+        }
+    }
+}
+
+// masks are rendered as hex since JSON numbers can't hold a u64 precisely
+pub fn format_mask(mask: u64) -> String {
+    format!("{:016x}", mask)
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct NotaryRoundSummary {
+    pub index: u8,
+    pub name: String,
+    pub bestk: Option<u8>,
+    pub bestmask: String,
+    pub recvmask: String,
+    pub lastseen: u32,
+    pub joined: u32,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct RoundSummary {
+    pub symbol: String,
+    pub height: u32,
+    pub srchash: String,
+    pub desthash: String,
+    pub consensus_bestk: Option<u8>,
+    pub consensus_bestmask: Option<String>,
+    pub consensus_votes: usize,
+    pub updated: u32,
+    pub started: u32,
+    pub converged: Option<u32>,
+    pub notaries: Vec<NotaryRoundSummary>,
+}
+
+// one (symbol, height) round, persisted to measure notarisation latency per chain
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct RoundRecord {
+    pub symbol: String,
+    pub height: u32,
+    pub started: u32,
+    pub converged: Option<u32>,
+    pub ended: Option<u32>,
+    // the consensus, or the agreed bestk/bestmask once converged
+    pub bestk: Option<u8>,
+    pub bestmask: Option<String>,
+    // notaries seen in the round
+    pub notaries: usize,
+}
+
+impl RoundRecord {
+    // seconds from the first message of the round until it converged
+    pub fn latency(&self) -> Option<u32> {
+        self.converged
+            .map(|converged| converged.saturating_sub(self.started))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HashKind {
+    Src,
+    Dest,
+}
+
+impl HashKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashKind::Src => "srchash",
+            HashKind::Dest => "desthash",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ForkSide {
+    pub hash: String,
+    pub notaries: Vec<String>,
+}
+
+// notaries disagreeing on the srchash or desthash of a (symbol, height), because of a
+// chain fork or a notary on a stale node
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Fork {
+    pub symbol: String,
+    pub height: u32,
+    // "srchash" or "desthash"
+    pub kind: String,
+    pub detected: u32,
+    pub updated: u32,
+    pub sides: Vec<ForkSide>,
+}
+
+// what an update changed, for the caller to persist
+#[derive(Clone, Debug, PartialEq)]
+pub enum RoundChange {
+    // a round started, converged or ended
+    Round(RoundRecord),
+    // the first message of a round from a notary
+    NotaryJoined {
+        symbol: String,
+        height: u32,
+        senderind: u8,
+        joined: u32,
+    },
+    // the sides of a disagreement changed; new_hash if a hash not seen before appeared
+    Fork {
+        fork: Fork,
+        new_hash: bool,
+    },
+}
+
+// in-memory view of the recent rounds per symbol, fed by every decoded message
+#[derive(Clone, Debug)]
+pub struct Rounds {
+    // the rounds of each symbol by height, at most `window` of them
+    symbols: HashMap<String, BTreeMap<u32, SymbolRound>>,
+    // signers a bestmask needs for the round to count as converged
+    required_sigs: usize,
+    window: usize,
+    max_symbols: usize,
+}
+
+impl Default for Rounds {
+    fn default() -> Self {
+        Rounds {
+            symbols: HashMap::new(),
+            required_sigs: DPOW_MINSIGS,
+            window: DEFAULT_ROUND_WINDOW,
+            max_symbols: DEFAULT_MAX_SYMBOLS,
+        }
+    }
+}
+
+impl Rounds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_required_sigs(mut self, required_sigs: usize) -> Self {
+        self.required_sigs = required_sigs;
+        self
+    }
+
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    pub fn with_max_symbols(mut self, max_symbols: usize) -> Self {
+        self.max_symbols = max_symbols.max(1);
+        self
+    }
+
+    pub fn update(&mut self, dpow_msg: &DpowNanoMsgHdr) -> Vec<RoundChange> {
+        self.update_at(dpow_msg, now_sec())
+    }
+
+    // as update, for a message received at an earlier time
+    pub fn update_at(&mut self, dpow_msg: &DpowNanoMsgHdr, now: u32) -> Vec<RoundChange> {
+        let mut changes = vec![];
+        let symbol = dpow_msg.symbol_str();
+        if symbol.is_empty() || dpow_msg.senderind as usize >= FIRST_PARTY.len() {
+            return changes;
+        }
+
+        if !self.symbols.contains_key(&symbol) && self.symbols.len() >= self.max_symbols {
+            self.evict_idle_symbol(now, &mut changes);
+        }
+        let heights = self.symbols.entry(symbol.clone()).or_default();
+        if !heights.contains_key(&dpow_msg.height) {
+            if heights.len() >= self.window {
+                // messages for a height older than every one kept are too late to count
+                let oldest = *heights.keys().next().unwrap();
+                if dpow_msg.height < oldest {
+                    return changes;
+                }
+                let mut evicted = heights.remove(&oldest).unwrap();
+                // only with a window of one is the evicted round still open
+                if evicted.ended.is_none() {
+                    evicted.ended = Some(now);
+                    changes.push(RoundChange::Round(evicted.record()));
+                }
+            }
+            let latest = heights.keys().next_back().copied();
+            // a late round for a height below the latest has already ended
+            let ended = latest
+                .filter(|latest| *latest > dpow_msg.height)
+                .map(|_| now);
+            if let Some(previous) = latest.and_then(|latest| heights.get_mut(&latest)) {
+                if ended.is_none() && previous.ended.is_none() {
+                    previous.ended = Some(now);
+                    changes.push(RoundChange::Round(previous.record()));
+                }
+            }
+            let round = SymbolRound::new(dpow_msg, symbol, now, ended);
+            changes.push(RoundChange::Round(round.record()));
+            heights.insert(dpow_msg.height, round);
+        }
+        let round = heights.get_mut(&dpow_msg.height).unwrap();
+        round.srchash = dpow_msg.srchash;
+        round.desthash = dpow_msg.desthash;
+        round.updated = now;
+        let sides_before = [round.sides(HashKind::Src), round.sides(HashKind::Dest)];
+        let joined = match round.notaries.get(&dpow_msg.senderind) {
+            Some(notary) => notary.joined,
+            None => {
+                changes.push(RoundChange::NotaryJoined {
+                    symbol: round.symbol.clone(),
+                    height: round.height,
+                    senderind: dpow_msg.senderind,
+                    joined: now,
+                });
+                now
+            }
+        };
+        round.notaries.insert(
+            dpow_msg.senderind,
+            NotaryRound {
+                bestk: dpow_msg.notarize.bestk,
+                bestmask: mask_to_u64(&dpow_msg.notarize.bestmask),
+                recvmask: mask_to_u64(&dpow_msg.notarize.recvmask),
+                lastseen: now,
+                joined,
+                srchash: dpow_msg.srchash,
+                desthash: dpow_msg.desthash,
+            },
+        );
+        for (kind, before) in [HashKind::Src, HashKind::Dest].iter().zip(sides_before) {
+            let sides = round.sides(*kind);
+            if sides.len() > 1 && sides != before {
+                changes.push(RoundChange::Fork {
+                    new_hash: sides
+                        .iter()
+                        .any(|side| before.iter().all(|old| old.hash != side.hash)),
+                    fork: Fork {
+                        symbol: round.symbol.clone(),
+                        height: round.height,
+                        kind: kind.name().to_string(),
+                        detected: now,
+                        updated: now,
+                        sides,
+                    },
+                });
+            }
+        }
+
+        if round.converged.is_none() && round.is_converged(self.required_sigs) {
+            round.converged = Some(now);
+            changes.push(RoundChange::Round(round.record()));
+        }
+        changes
+    }
+
+    // drops the symbol updated least recently, ending its open round
+    fn evict_idle_symbol(&mut self, now: u32, changes: &mut Vec<RoundChange>) {
+        let idle = self
+            .symbols
+            .iter()
+            .min_by_key(|(_, heights)| heights.values().map(|round| round.updated).max())
+            .map(|(symbol, _)| symbol.clone());
+        let heights = match idle.and_then(|symbol| self.symbols.remove(&symbol)) {
+            Some(heights) => heights,
+            None => return,
+        };
+        for mut round in heights.into_values() {
+            if round.ended.is_none() {
+                round.ended = Some(now);
+                changes.push(RoundChange::Round(round.record()));
+            }
+        }
+    }
+
+    // the round at the highest height seen for symbol
+    pub fn get(&self, symbol: &str) -> Option<&SymbolRound> {
+        self.symbols
+            .get(symbol)
+            .and_then(|heights| heights.values().next_back())
+    }
+
+    pub fn get_height(&self, symbol: &str, height: u32) -> Option<&SymbolRound> {
+        self.symbols
+            .get(symbol)
+            .and_then(|heights| heights.get(&height))
+    }
+
+    // the latest round of every symbol
+    pub fn summaries(&self) -> Vec<RoundSummary> {
+        let mut summaries: Vec<RoundSummary> = self
+            .symbols
+            .keys()
+            .filter_map(|symbol| self.get(symbol))
+            .map(|round| round.summary())
+            .collect();
+        summaries.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        summaries
+    }
+}
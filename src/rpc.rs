@@ -0,0 +1,383 @@
+use crate::config::{RpcAuth, RpcConfig};
+use crate::db::{
+    get_chain_tip, get_chain_tips, get_forks, get_ip_history, get_known_ips, get_notaries,
+    get_notary_by_id, get_notary_by_name, get_notary_utxos, get_round_history, get_spam_incidents,
+    NotaryInfo,
+};
+use crate::participation::notary_participation;
+use crate::peers::{PeerCommand, PeerRequest};
+use crate::rounds::Rounds;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::future;
+use jsonrpc_core::types::error::{Error, ErrorCode};
+use jsonrpc_core::types::params::Params;
+use jsonrpc_core::types::Value;
+use jsonrpc_core::{MetaIoHandler, Metadata};
+use jsonrpc_http_server::hyper::header::{HeaderValue, AUTHORIZATION};
+use jsonrpc_http_server::hyper::{Body, Method, Request, StatusCode};
+use jsonrpc_http_server::{
+    AccessControlAllowOrigin, DomainsValidation, Host, Response, Server, ServerBuilder,
+};
+use rusqlite::Connection;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Role {
+    #[default]
+    Read,
+    Admin,
+}
+
+// per request metadata, filled in from the Authorization header
+#[derive(Clone, Debug, Default)]
+pub struct RpcMeta {
+    pub role: Role,
+}
+
+impl Metadata for RpcMeta {}
+
+pub type RpcHandler = MetaIoHandler<RpcMeta>;
+
+impl RpcAuth {
+    // the exact Authorization header value a client must send
+    pub fn header_value(&self) -> String {
+        match self {
+            RpcAuth::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    BASE64.encode(format!("{}:{}", username, password))
+                )
+            }
+            RpcAuth::Bearer(token) => format!("Bearer {}", token),
+        }
+    }
+
+    pub fn matches(&self, header: Option<&HeaderValue>) -> bool {
+        match header {
+            Some(header) => constant_time_eq(header.as_bytes(), self.header_value().as_bytes()),
+            None => false,
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// the role granted by a request's Authorization header, None if it should be rejected
+pub fn request_role(config: &RpcConfig, header: Option<&HeaderValue>) -> Option<Role> {
+    if let Some(admin_auth) = &config.admin_auth {
+        if admin_auth.matches(header) {
+            return Some(Role::Admin);
+        }
+    }
+    if let Some(read_auth) = &config.read_auth {
+        if !read_auth.matches(header) {
+            return None;
+        }
+    }
+    match config.admin_auth {
+        Some(_) => Some(Role::Read),
+        None => Some(Role::Admin),
+    }
+}
+
+// methods that change listener state are only served to the admin role
+pub fn add_admin_method<F>(io: &mut RpcHandler, name: &str, method: F)
+where
+    F: Fn(Params) -> Result<Value, Error> + Send + Sync + 'static,
+{
+    let unauthorized = format!("'{}' requires admin credentials", name);
+    io.add_method_with_meta(name, move |params: Params, meta: RpcMeta| {
+        future::ready(match meta.role {
+            Role::Admin => method(params),
+            Role::Read => Err(Error {
+                code: ErrorCode::ServerError(-32001),
+                message: unauthorized.clone(),
+                data: None,
+            }),
+        })
+    });
+}
+
+pub fn start_rpc_server(io: RpcHandler, config: &RpcConfig) -> io::Result<Server> {
+    let addr: SocketAddr = config
+        .bind
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    let auth_config = config.clone();
+    let meta_config = config.clone();
+    let mut builder =
+        ServerBuilder::with_meta_extractor(io, move |request: &Request<Body>| RpcMeta {
+            role: request_role(&meta_config, request.headers().get(AUTHORIZATION))
+                .unwrap_or_default(),
+        })
+        .request_middleware(move |request: Request<Body>| {
+            // CORS preflight requests never carry credentials
+            if request.method() == Method::OPTIONS
+                || request_role(&auth_config, request.headers().get(AUTHORIZATION)).is_some()
+            {
+                request.into()
+            } else {
+                Response {
+                    code: StatusCode::UNAUTHORIZED,
+                    content_type: HeaderValue::from_static("text/plain; charset=utf-8"),
+                    content: "Unauthorized\n".to_string(),
+                }
+                .into()
+            }
+        })
+        .threads(3);
+
+    if let Some(cors) = &config.cors {
+        let origins = cors.iter().map(AccessControlAllowOrigin::from).collect();
+        builder = builder.cors(DomainsValidation::AllowOnly(origins));
+    }
+    if let Some(allowed_hosts) = &config.allowed_hosts {
+        let hosts = allowed_hosts.iter().map(Host::from).collect();
+        builder = builder.allowed_hosts(DomainsValidation::AllowOnly(hosts));
+    }
+    builder.start_http(&addr)
+}
+
+// notaries can be looked up either by {"name": "alright_EU"} or by {"index": 8}
+fn notary_from_params(conn: &Connection, params: Params) -> Result<NotaryInfo, Error> {
+    let map = match params {
+        Params::Map(map) => map,
+        _ => return Err(Error::invalid_params("Expected map")),
+    };
+    let notary = match (map.get("name"), map.get("index")) {
+        (Some(Value::String(name)), _) => get_notary_by_name(conn, name),
+        (_, Some(Value::Number(index))) => match index.as_u64() {
+            Some(index) if index < 64 => get_notary_by_id(conn, index as u8),
+            _ => return Err(Error::invalid_params("'index' must be between 0 and 63")),
+        },
+        _ => return Err(Error::invalid_params("Missing 'name' or 'index'")),
+    };
+    notary.ok_or_else(|| Error::invalid_params("Unknown notary"))
+}
+
+// an optional u32 param, an error if it is given as anything else
+fn u32_param(params: &Params, name: &str, default: u32) -> Result<u32, Error> {
+    let value = match params {
+        Params::Map(map) => map.get(name),
+        _ => None,
+    };
+    match value {
+        None => Ok(default),
+        Some(value) => value
+            .as_u64()
+            .and_then(|value| u32::try_from(value).ok())
+            .ok_or_else(|| {
+                Error::invalid_params(format!("'{}' must be between 0 and {}", name, u32::MAX))
+            }),
+    }
+}
+
+// read-only methods backed by the listener's sqlite db
+pub fn add_query_methods(io: &mut RpcHandler, conn: Arc<Mutex<Connection>>) {
+    let conn_notaries = conn.clone();
+    io.add_method("get_notaries", move |_params: Params| {
+        let notaries = get_notaries(&conn_notaries.lock().unwrap());
+        future::ready(Ok(serde_json::to_value(notaries).unwrap()))
+    });
+
+    let conn_notary = conn.clone();
+    io.add_method("get_notary", move |params: Params| {
+        let notary = notary_from_params(&conn_notary.lock().unwrap(), params);
+        future::ready(notary.map(|notary| serde_json::to_value(notary).unwrap()))
+    });
+
+    let conn_ip_history = conn.clone();
+    io.add_method("get_ip_history", move |params: Params| {
+        let conn = conn_ip_history.lock().unwrap();
+        let history = notary_from_params(&conn, params)
+            .map(|notary| serde_json::to_value(get_ip_history(&conn, notary.id)).unwrap());
+        future::ready(history)
+    });
+
+    let conn_known_ips = conn.clone();
+    io.add_method("get_known_ips", move |_params: Params| {
+        let known_ips = get_known_ips(&conn_known_ips.lock().unwrap());
+        future::ready(Ok(serde_json::to_value(known_ips).unwrap()))
+    });
+
+    // {"symbol": "KMD", "limit": 10}, most recent first; limit defaults to 100
+    let conn_round_history = conn.clone();
+    io.add_method("get_round_history", move |params: Params| {
+        let limit = u32_param(&params, "limit", 100);
+        future::ready(match &params {
+            Params::Map(map) => match map.get("symbol") {
+                Some(Value::String(symbol)) => limit.map(|limit| {
+                    let history =
+                        get_round_history(&conn_round_history.lock().unwrap(), symbol, limit);
+                    serde_json::to_value(history).unwrap()
+                }),
+                _ => Err(Error::invalid_params("Missing 'symbol'")),
+            },
+            _ => Err(Error::invalid_params("Expected map")),
+        })
+    });
+
+    // {"symbol": "KMD", "limit": 10}, both optional; most recently updated first
+    let conn_forks = conn.clone();
+    io.add_method("get_forks", move |params: Params| {
+        let symbol = match &params {
+            Params::Map(map) => map.get("symbol").and_then(Value::as_str),
+            _ => None,
+        };
+        let forks = u32_param(&params, "limit", 100).map(|limit| {
+            serde_json::to_value(get_forks(&conn_forks.lock().unwrap(), symbol, limit)).unwrap()
+        });
+        future::ready(forks)
+    });
+
+    // {"symbol": "KMD"} for one symbol, otherwise every symbol notaries have reported
+    let conn_tips = conn.clone();
+    io.add_method("get_chain_tips", move |params: Params| {
+        let symbol = match &params {
+            Params::Map(map) => map.get("symbol").and_then(Value::as_str),
+            _ => None,
+        };
+        let conn = conn_tips.lock().unwrap();
+        let tips = match symbol {
+            Some(symbol) => get_chain_tip(&conn, symbol).into_iter().collect(),
+            None => get_chain_tips(&conn),
+        };
+        future::ready(Ok(serde_json::to_value(tips).unwrap()))
+    });
+
+    // {"from": 1700000000, "to": 1700086400} in unix time, by default every round
+    let conn_participation = conn.clone();
+    io.add_method("get_notary_participation", move |params: Params| {
+        let range = u32_param(&params, "from", 0)
+            .and_then(|from| Ok((from, u32_param(&params, "to", u32::MAX)?)));
+        let stats = range.map(|(from, to)| {
+            let stats = notary_participation(&conn_participation.lock().unwrap(), from, to);
+            serde_json::to_value(stats).unwrap()
+        });
+        future::ready(stats)
+    });
+
+    // {"symbol": "KMD", "problems": true, "limit": 10}, all optional; problems for only
+    // UTXOs offered by more than one notary and notaries that offered none
+    let conn_utxos = conn.clone();
+    io.add_method("get_utxos", move |params: Params| {
+        let (symbol, problems) = match &params {
+            Params::Map(map) => (
+                map.get("symbol").and_then(Value::as_str),
+                map.get("problems")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+            ),
+            _ => (None, false),
+        };
+        let utxos = u32_param(&params, "limit", 100).map(|limit| {
+            let utxos = get_notary_utxos(&conn_utxos.lock().unwrap(), symbol, problems, limit);
+            serde_json::to_value(utxos).unwrap()
+        });
+        future::ready(utxos)
+    });
+
+    // {"limit": 10} for fewer than the default 100 most recent
+    io.add_method("get_spam_incidents", move |params: Params| {
+        let incidents = u32_param(&params, "limit", 100).map(|limit| {
+            serde_json::to_value(get_spam_incidents(&conn.lock().unwrap(), limit)).unwrap()
+        });
+        future::ready(incidents)
+    });
+}
+
+// live dPoW round state maintained by the listener thread
+pub fn add_round_methods(io: &mut RpcHandler, rounds: Arc<Mutex<Rounds>>) {
+    let rounds_all = rounds.clone();
+    io.add_method("get_rounds", move |_params: Params| {
+        let summaries = rounds_all.lock().unwrap().summaries();
+        future::ready(Ok(serde_json::to_value(summaries).unwrap()))
+    });
+
+    io.add_method("get_round", move |params: Params| {
+        future::ready(match &params {
+            Params::Map(map) => {
+                if let Some(Value::String(symbol)) = map.get("symbol") {
+                    // the latest round unless a height is given
+                    let rounds = rounds.lock().unwrap();
+                    let round = match map.get("height") {
+                        Some(_) => u32_param(&params, "height", 0)
+                            .map(|height| rounds.get_height(symbol, height)),
+                        None => Ok(rounds.get(symbol)),
+                    };
+                    match round {
+                        Ok(Some(round)) => Ok(serde_json::to_value(round.summary()).unwrap()),
+                        Ok(None) => Err(Error::invalid_params("Unknown symbol or height")),
+                        Err(err) => Err(err),
+                    }
+                } else {
+                    Err(Error::invalid_params("Missing 'symbol'"))
+                }
+            }
+            _ => Err(Error::invalid_params("Expected map")),
+        })
+    });
+}
+
+// hand a peer command to the listener thread and wait for it to be applied
+fn send_peer_command(
+    peers: &Mutex<Sender<PeerRequest>>,
+    command: PeerCommand,
+) -> Result<Value, Error> {
+    let (reply, response) = channel();
+    peers
+        .lock()
+        .unwrap()
+        .send(PeerRequest { command, reply })
+        .map_err(|_| Error::internal_error())?;
+    match response.recv_timeout(Duration::from_secs(5)) {
+        Ok(Ok(peer_list)) => Ok(serde_json::to_value(peer_list).unwrap()),
+        Ok(Err(message)) => Err(Error::invalid_params(message)),
+        Err(_) => Err(Error::internal_error()),
+    }
+}
+
+fn ip_from_params(params: Params) -> Result<String, Error> {
+    match params {
+        Params::Map(map) => match map.get("ip") {
+            Some(Value::String(ip)) => Ok(ip.clone()),
+            _ => Err(Error::invalid_params("Missing 'ip'")),
+        },
+        _ => Err(Error::invalid_params("Expected map")),
+    }
+}
+
+fn add_ip_method(
+    io: &mut RpcHandler,
+    name: &str,
+    peers: Arc<Mutex<Sender<PeerRequest>>>,
+    command: fn(String) -> PeerCommand,
+) {
+    add_admin_method(io, name, move |params: Params| {
+        ip_from_params(params).and_then(|ip| send_peer_command(&peers, command(ip)))
+    });
+}
+
+// peer administration, executed on the live bus socket by the listener thread
+// list_peers is read-only, everything else requires the admin role. ban_ip disconnects
+// and stops dialling the IP and stops recording it from notaries' ipbits, but packets
+// it sends through other peers are still processed
+pub fn add_peer_methods(io: &mut RpcHandler, peers: Sender<PeerRequest>) {
+    let peers = Arc::new(Mutex::new(peers));
+    add_ip_method(io, "add_peer", peers.clone(), PeerCommand::Add);
+    add_ip_method(io, "remove_peer", peers.clone(), PeerCommand::Remove);
+    add_ip_method(io, "ban_ip", peers.clone(), PeerCommand::Ban);
+    add_ip_method(io, "unban_ip", peers.clone(), PeerCommand::Unban);
+
+    io.add_method("list_peers", move |_params: Params| {
+        future::ready(send_peer_command(&peers, PeerCommand::List))
+    });
+}
@@ -0,0 +1,174 @@
+use crate::bestk::dpow_bestk;
+use crate::packet::{dpow_msg_size, encode_packet};
+use crate::{DpowNanoMsgHdr, DPOW_MINSIGS, DPOW_SIGCHANNEL, FIRST_PARTY};
+use bincode::Options;
+use secp256k1::SecretKey;
+use sha2::{Digest, Sha256};
+
+// fake notaries producing signed dPoW traffic, for testing the listener without
+// joining the real notary network. the packets are plain bytes so they can be
+// sent over a bus socket by iguana_rs_simulator or fed straight into a Pipeline
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    // zeroed signature, which recovers no public key
+    BadSignature,
+    // signed by one notary while claiming to be another
+    WrongSenderind,
+    // cut off halfway through the payload
+    Truncated,
+}
+
+impl Fault {
+    pub const ALL: [Fault; 3] = [Fault::BadSignature, Fault::WrongSenderind, Fault::Truncated];
+}
+
+#[derive(Clone, Debug)]
+pub struct SimNotary {
+    pub senderind: u8,
+    pub sk: SecretKey,
+    pub myipbits: [u8; 4],
+    // notaries this one has received a message from in the current round
+    pub recvmask: u64,
+}
+
+// deterministic key for a simulated notary, never use these on a real network
+pub fn test_secret_key(senderind: u8) -> SecretKey {
+    let seed: [u8; 32] = Sha256::digest([b'i', b'g', b'u', b'a', b'n', b'a', senderind]).into();
+    SecretKey::from_slice(&seed).unwrap()
+}
+
+pub fn blank_msg() -> DpowNanoMsgHdr {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .deserialize(&vec![0u8; dpow_msg_size()])
+        .unwrap()
+}
+
+pub struct Simulator {
+    pub symbol: String,
+    pub height: u32,
+    pub notaries: Vec<SimNotary>,
+    // number of steps taken in the current round
+    step: usize,
+}
+
+impl Simulator {
+    pub fn new(numnotaries: usize, symbol: &str, height: u32) -> Self {
+        assert!(numnotaries > 0 && numnotaries <= FIRST_PARTY.len());
+        assert!(symbol.len() < 16);
+        let notaries = (0..numnotaries as u8)
+            .map(|senderind| SimNotary {
+                senderind,
+                sk: test_secret_key(senderind),
+                // loopback, so a listener dialling the advertised IPs stays on this host
+                myipbits: [127, 0, 0, senderind + 1],
+                recvmask: 0,
+            })
+            .collect();
+        Simulator {
+            symbol: symbol.to_string(),
+            height,
+            notaries,
+            step: 0,
+        }
+    }
+
+    pub fn numnotaries(&self) -> usize {
+        self.notaries.len()
+    }
+
+    // the current message of one notary
+    pub fn message(&self, senderind: u8) -> DpowNanoMsgHdr {
+        let notary = &self.notaries[senderind as usize];
+        let mut msg = blank_msg();
+        msg.symbol[..self.symbol.len()].copy_from_slice(self.symbol.as_bytes());
+        msg.senderind = senderind;
+        msg.channel = DPOW_SIGCHANNEL;
+        msg.height = self.height;
+        msg.srchash = Sha256::digest(format!("{}:{}", self.symbol, self.height)).into();
+        msg.desthash = Sha256::digest(format!("KMD:{}", self.height)).into();
+        msg.myipbits = notary.myipbits;
+
+        // each notary advertises itself, its two neighbours and one peer that changes every round
+        let numnotaries = self.numnotaries();
+        let offsets = [0, 1, numnotaries - 1, 2 + (self.height / 10) as usize];
+        for (i, offset) in offsets.iter().enumerate() {
+            let peer = (senderind as usize + offset) % numnotaries;
+            msg.ipbits[i] = self.notaries[peer].myipbits;
+        }
+        msg.numipbits = offsets.len() as u32;
+
+        msg.notarize.recvmask = notary.recvmask.to_le_bytes();
+        let (bestk, bestmask) = self.best(notary.recvmask);
+        msg.notarize.bestk = bestk;
+        msg.notarize.bestmask = bestmask.to_le_bytes();
+        msg
+    }
+
+    // what iguana would choose with this recvmask, see bestk.rs
+    fn best(&self, recvmask: u64) -> (u8, u64) {
+        let numnotaries = self.numnotaries();
+        dpow_bestk(
+            recvmask,
+            self.height,
+            numnotaries,
+            DPOW_MINSIGS.min(numnotaries),
+        )
+    }
+
+    // one signed packet from every notary, then each notary receives one more peer
+    // once every notary has heard from every other the round moves 10 blocks on
+    pub fn step(&mut self) -> Vec<Vec<u8>> {
+        let packets = (0..self.numnotaries() as u8)
+            .map(|senderind| {
+                encode_packet(
+                    &self.message(senderind),
+                    &[],
+                    &self.notaries[senderind as usize].sk,
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let numnotaries = self.numnotaries();
+        self.step += 1;
+        if self.step > numnotaries {
+            self.step = 0;
+            self.height += 10;
+            for notary in self.notaries.iter_mut() {
+                notary.recvmask = 0;
+            }
+        } else {
+            for notary in self.notaries.iter_mut() {
+                let peer = (notary.senderind as usize + self.step - 1) % numnotaries;
+                notary.recvmask |= 1 << peer;
+            }
+        }
+        packets
+    }
+
+    // a packet from senderind with the given fault
+    pub fn faulty_packet(&self, senderind: u8, fault: Fault) -> Vec<u8> {
+        let msg = self.message(senderind);
+        let sk = &self.notaries[senderind as usize].sk;
+        match fault {
+            Fault::BadSignature => {
+                let mut packet = encode_packet(&msg, &[], sk).unwrap();
+                // any other r would still recover some public key that verifies
+                packet[..64].fill(0);
+                packet
+            }
+            Fault::WrongSenderind => {
+                let mut msg = msg;
+                msg.senderind = ((senderind as usize + 1) % self.numnotaries()) as u8;
+                encode_packet(&msg, &[], sk).unwrap()
+            }
+            Fault::Truncated => {
+                let mut packet = encode_packet(&msg, &[], sk).unwrap();
+                packet.truncate(packet.len() / 2);
+                packet
+            }
+        }
+    }
+}
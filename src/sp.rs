@@ -0,0 +1,358 @@
+use crate::transport::Transport;
+use std::collections::HashMap;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// native implementation of the nanomsg Bus protocol over TCP, enough to talk to iguana
+//
+// every connection starts with both sides sending an 8 byte SP header:
+//   0x00 'S' 'P' 0x00, protocol id as u16 big endian (Bus is 112), 2 reserved zero bytes
+// after which each message is a u64 big endian length followed by the message bytes.
+// a bus sends every message to all connected peers and, like libnanomsg, dialled
+// endpoints are redialled until they are disconnected
+pub const SP_BUS_PROTOCOL: u16 = 112;
+// libnanomsg's default NN_RCVMAXSIZE
+pub const DEFAULT_RECEIVE_MAX_SIZE: usize = 1024 * 1024;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// a peer that stops reading is dropped rather than blocking send for everyone
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_IVL: Duration = Duration::from_millis(100);
+const RECONNECT_IVL_MAX: Duration = Duration::from_secs(5);
+// how often the accept thread checks whether the bus was dropped
+const ACCEPT_POLL_IVL: Duration = Duration::from_millis(50);
+// messages queued for a peer before it is dropped as too slow
+const SEND_QUEUE: usize = 1024;
+// accepted connections, counting those still handshaking, beyond which new ones are closed.
+// libnanomsg only bounds them by its listen backlog of 100, iguana has at most 64 notaries
+pub const DEFAULT_MAX_ACCEPTED: usize = 128;
+
+pub fn sp_header(protocol: u16) -> [u8; 8] {
+    let id = protocol.to_be_bytes();
+    [0, b'S', b'P', 0, id[0], id[1], 0, 0]
+}
+
+// strips the tcp:// prefix, other nanomsg transports are not supported
+fn tcp_addr(addr: &str) -> io::Result<&str> {
+    addr.strip_prefix("tcp://").ok_or_else(|| {
+        io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("unsupported address {}, only tcp:// is supported", addr),
+        )
+    })
+}
+
+fn channel_closed() -> io::Error {
+    io::Error::new(ErrorKind::BrokenPipe, "bus message channel closed")
+}
+
+// connects with a timeout, so a dial thread never blocks for long on an unreachable host
+fn dial(addr: &str) -> io::Result<TcpStream> {
+    let mut last_err = io::Error::new(ErrorKind::NotFound, format!("cannot resolve {}", addr));
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, HANDSHAKE_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+// a live connection, written to by its own thread so a stalled peer can't block send
+struct Pipe {
+    stream: TcpStream,
+    queue: SyncSender<Arc<Vec<u8>>>,
+}
+
+// state shared with the threads running each connection
+struct Shared {
+    pipes: Mutex<HashMap<u64, Pipe>>,
+    next_pipe: AtomicU64,
+    receive_max_size: AtomicUsize,
+    accepted: AtomicUsize,
+    max_accepted: AtomicUsize,
+}
+
+impl Shared {
+    // handshakes, then forwards messages until the connection fails
+    fn run_pipe(&self, mut stream: TcpStream, messages: &Sender<Vec<u8>>) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.write_all(&sp_header(SP_BUS_PROTOCOL))?;
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header)?;
+        if header != sp_header(SP_BUS_PROTOCOL) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "peer is not an SP bus socket",
+            ));
+        }
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(Some(SEND_TIMEOUT))?;
+
+        let (queue, frames) = sync_channel::<Arc<Vec<u8>>>(SEND_QUEUE);
+        let mut writer = stream.try_clone()?;
+        let pipe_stream = stream.try_clone()?;
+        // ends once the pipe is removed and its queue dropped, or a write fails
+        thread::spawn(move || {
+            for frame in frames {
+                if writer.write_all(&frame).is_err() {
+                    let _ = writer.shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+        });
+        let pipe = self.next_pipe.fetch_add(1, Ordering::Relaxed);
+        self.pipes.lock().unwrap().insert(
+            pipe,
+            Pipe {
+                stream: pipe_stream,
+                queue,
+            },
+        );
+        let result = self.read_messages(&mut stream, messages);
+        self.pipes.lock().unwrap().remove(&pipe);
+        result
+    }
+
+    fn read_messages(&self, stream: &mut TcpStream, messages: &Sender<Vec<u8>>) -> io::Result<()> {
+        loop {
+            let mut len = [0u8; 8];
+            stream.read_exact(&mut len)?;
+            let len = u64::from_be_bytes(len);
+            // libnanomsg drops the connection on oversized messages too
+            if len > self.receive_max_size.load(Ordering::Relaxed) as u64 {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("message of {} bytes exceeds the receive max size", len),
+                ));
+            }
+            let mut message = vec![0u8; len as usize];
+            stream.read_exact(&mut message)?;
+            if messages.send(message).is_err() {
+                // the SpBus was dropped
+                return Ok(());
+            }
+        }
+    }
+}
+
+// a dialled endpoint, stopped by disconnect
+struct DialEndpoint {
+    stop: Arc<AtomicBool>,
+    // the current connection, if any, so disconnect can close it
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    thread: JoinHandle<()>,
+}
+
+// sleeps for ivl, returning early once stop is set
+fn sleep_unless(stop: &AtomicBool, ivl: Duration) {
+    let mut slept = Duration::ZERO;
+    while slept < ivl && !stop.load(Ordering::Relaxed) {
+        thread::sleep(RECONNECT_IVL.min(ivl - slept));
+        slept += RECONNECT_IVL;
+    }
+}
+
+// dropping the bus closes every connection and joins the accept and dial threads
+pub struct SpBus {
+    shared: Arc<Shared>,
+    sender: Sender<Vec<u8>>,
+    messages: Receiver<Vec<u8>>,
+    endpoints: HashMap<String, DialEndpoint>,
+    receive_timeout: Option<Duration>,
+    shutdown: Arc<AtomicBool>,
+    accept_threads: Vec<JoinHandle<()>>,
+    // dial threads of disconnected endpoints that may still be connecting
+    stopping: Vec<JoinHandle<()>>,
+}
+
+impl Default for SpBus {
+    fn default() -> Self {
+        let (sender, messages) = channel();
+        SpBus {
+            shared: Arc::new(Shared {
+                pipes: Mutex::new(HashMap::new()),
+                next_pipe: AtomicU64::new(0),
+                receive_max_size: AtomicUsize::new(DEFAULT_RECEIVE_MAX_SIZE),
+                accepted: AtomicUsize::new(0),
+                max_accepted: AtomicUsize::new(DEFAULT_MAX_ACCEPTED),
+            }),
+            sender,
+            messages,
+            endpoints: HashMap::new(),
+            receive_timeout: None,
+            shutdown: Arc::new(AtomicBool::new(false)),
+            accept_threads: vec![],
+            stopping: vec![],
+        }
+    }
+}
+
+impl SpBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // None blocks until a message arrives
+    pub fn set_receive_timeout(&mut self, timeout: Option<Duration>) {
+        self.receive_timeout = timeout;
+    }
+
+    pub fn set_receive_max_size(&mut self, size: usize) {
+        self.shared.receive_max_size.store(size, Ordering::Relaxed);
+    }
+
+    pub fn set_max_accepted(&mut self, max: usize) {
+        self.shared.max_accepted.store(max, Ordering::Relaxed);
+    }
+
+    // number of peers with a completed handshake
+    pub fn connected_pipes(&self) -> usize {
+        self.shared.pipes.lock().unwrap().len()
+    }
+
+    // accepts connections on a background thread; returns the bound address so port 0 can be used
+    pub fn bind(&mut self, addr: &str) -> io::Result<String> {
+        let listener = TcpListener::bind(tcp_addr(addr)?)?;
+        let local_addr = format!("tcp://{}", listener.local_addr()?);
+        // polled so the thread notices the bus being dropped
+        listener.set_nonblocking(true)?;
+        let shared = self.shared.clone();
+        let sender = self.sender.clone();
+        let shutdown = self.shutdown.clone();
+        self.accept_threads.push(thread::spawn(move || {
+            while !shutdown.load(Ordering::Relaxed) {
+                let stream = match listener.accept() {
+                    Ok((stream, _)) => stream,
+                    Err(_) => {
+                        thread::sleep(ACCEPT_POLL_IVL);
+                        continue;
+                    }
+                };
+                // over the limit the connection is closed by dropping it
+                let max_accepted = shared.max_accepted.load(Ordering::Relaxed);
+                if shared.accepted.fetch_add(1, Ordering::SeqCst) >= max_accepted {
+                    shared.accepted.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                let shared = shared.clone();
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    if stream.set_nonblocking(false).is_ok() {
+                        let _ = shared.run_pipe(stream, &sender);
+                    }
+                    shared.accepted.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        }));
+        Ok(local_addr)
+    }
+}
+
+impl Transport for SpBus {
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        // self.sender keeps the channel open, so it never disconnects
+        match self.receive_timeout {
+            Some(timeout) => match self.messages.recv_timeout(timeout) {
+                Ok(message) => Ok(Some(message)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(channel_closed()),
+            },
+            None => self.messages.recv().map(Some).map_err(|_| channel_closed()),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut frame = (data.len() as u64).to_be_bytes().to_vec();
+        frame.extend_from_slice(data);
+        let frame = Arc::new(frame);
+        // a peer too far behind is closed here and cleaned up by its reading thread
+        for pipe in self.shared.pipes.lock().unwrap().values() {
+            if pipe.queue.try_send(frame.clone()).is_err() {
+                let _ = pipe.stream.shutdown(Shutdown::Both);
+            }
+        }
+        Ok(())
+    }
+
+    fn connect(&mut self, addr: &str) -> io::Result<()> {
+        if self.endpoints.contains_key(addr) {
+            return Ok(());
+        }
+        let tcp_addr = tcp_addr(addr)?.to_string();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stream = Arc::new(Mutex::new(None));
+        let (thread_stop, current) = (stop.clone(), stream.clone());
+        let shared = self.shared.clone();
+        let sender = self.sender.clone();
+        let thread = thread::spawn(move || {
+            let stop = thread_stop;
+            let mut ivl = RECONNECT_IVL;
+            while !stop.load(Ordering::Relaxed) {
+                if let Ok(stream) = dial(&tcp_addr) {
+                    ivl = RECONNECT_IVL;
+                    if let Ok(clone) = stream.try_clone() {
+                        *current.lock().unwrap() = Some(clone);
+                        // checked again in case disconnect ran while connecting
+                        if !stop.load(Ordering::Relaxed) {
+                            let _ = shared.run_pipe(stream, &sender);
+                        }
+                        *current.lock().unwrap() = None;
+                    }
+                }
+                sleep_unless(&stop, ivl);
+                ivl = (ivl * 2).min(RECONNECT_IVL_MAX);
+            }
+        });
+        let endpoint = DialEndpoint {
+            stop,
+            stream,
+            thread,
+        };
+        self.endpoints.insert(addr.to_string(), endpoint);
+        Ok(())
+    }
+
+    // the dial thread may still be connecting, it is left to stop on its own rather than
+    // waited for here, and joined when the bus is dropped
+    fn disconnect(&mut self, addr: &str) -> io::Result<()> {
+        match self.endpoints.remove(addr) {
+            Some(endpoint) => {
+                endpoint.stop.store(true, Ordering::Relaxed);
+                if let Some(stream) = endpoint.stream.lock().unwrap().as_ref() {
+                    let _ = stream.shutdown(Shutdown::Both);
+                }
+                self.stopping.retain(|thread| !thread.is_finished());
+                self.stopping.push(endpoint.thread);
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("not connected to {}", addr),
+            )),
+        }
+    }
+}
+
+impl Drop for SpBus {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        let dialled: Vec<String> = self.endpoints.keys().cloned().collect();
+        for addr in dialled {
+            let _ = self.disconnect(&addr);
+        }
+        // the threads reading accepted connections end once their stream is closed
+        for pipe in self.shared.pipes.lock().unwrap().values() {
+            let _ = pipe.stream.shutdown(Shutdown::Both);
+        }
+        for thread in self.accept_threads.drain(..).chain(self.stopping.drain(..)) {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use crate::db::{
+    ban_ip, get_banned_ips, get_known_ips, insert_spam_incident, save_fork, save_notary_utxo,
+    save_round, save_round_notary, unban_ip, update_ip_logs_at, update_known_ips_at,
+    update_lastseen_at, update_notary_height, NotaryUtxo, SpamIncident,
+};
+use crate::rounds::RoundChange;
+use rusqlite::Connection;
+
+// where the listener records what it learns from valid messages
+// times are unix seconds; the sqlite db from db.rs is the default backend
+pub trait Storage: Send {
+    fn update_lastseen(&self, notary_id: u8, lastseen: u32);
+    fn update_ip_logs(&self, notary_id: u8, ipbits: [u8; 4], now: u32);
+    // returns the IPs that were not known before
+    fn update_known_ips(&self, notary_id: u8, ips: Vec<[u8; 4]>, now: u32) -> Vec<String>;
+    // every IP learned from ipbits, dialled on startup
+    fn known_ips(&self) -> Vec<String>;
+    fn banned_ips(&self) -> Vec<String>;
+    fn ban_ip(&self, ip: &str);
+    fn unban_ip(&self, ip: &str);
+    fn record_incident(&self, incident: &SpamIncident);
+    fn save_round_change(&self, change: &RoundChange);
+    // the height and srchash a notary is working on for a symbol
+    fn update_notary_height(
+        &self,
+        symbol: &str,
+        notary_id: u8,
+        height: u32,
+        srchash: [u8; 32],
+        now: u32,
+    );
+    // a UTXO a notary offered for a round, or its lack of one
+    fn save_utxo(&self, utxo: &NotaryUtxo);
+}
+
+impl Storage for Connection {
+    fn update_lastseen(&self, notary_id: u8, lastseen: u32) {
+        update_lastseen_at(self, notary_id, lastseen);
+    }
+
+    fn update_ip_logs(&self, notary_id: u8, ipbits: [u8; 4], now: u32) {
+        update_ip_logs_at(self, notary_id, ipbits, now);
+    }
+
+    fn update_known_ips(&self, notary_id: u8, ips: Vec<[u8; 4]>, now: u32) -> Vec<String> {
+        update_known_ips_at(self, notary_id, ips, now)
+    }
+
+    fn known_ips(&self) -> Vec<String> {
+        get_known_ips(self)
+            .into_iter()
+            .map(|known| known.ip)
+            .collect()
+    }
+
+    fn banned_ips(&self) -> Vec<String> {
+        get_banned_ips(self)
+    }
+
+    fn ban_ip(&self, ip: &str) {
+        ban_ip(self, ip);
+    }
+
+    fn unban_ip(&self, ip: &str) {
+        unban_ip(self, ip);
+    }
+
+    fn record_incident(&self, incident: &SpamIncident) {
+        insert_spam_incident(self, incident);
+    }
+
+    fn save_round_change(&self, change: &RoundChange) {
+        match change {
+            RoundChange::Round(round) => save_round(self, round),
+            RoundChange::NotaryJoined {
+                symbol,
+                height,
+                senderind,
+                joined,
+            } => save_round_notary(self, symbol, *height, *senderind, *joined),
+            RoundChange::Fork { fork, .. } => save_fork(self, fork),
+        }
+    }
+
+    fn update_notary_height(
+        &self,
+        symbol: &str,
+        notary_id: u8,
+        height: u32,
+        srchash: [u8; 32],
+        now: u32,
+    ) {
+        update_notary_height(self, symbol, notary_id, height, srchash, now);
+    }
+
+    fn save_utxo(&self, utxo: &NotaryUtxo) {
+        save_notary_utxo(self, utxo);
+    }
+}
+
+// lets the backend be picked at runtime
+impl Storage for Box<dyn Storage> {
+    fn update_lastseen(&self, notary_id: u8, lastseen: u32) {
+        (**self).update_lastseen(notary_id, lastseen);
+    }
+
+    fn update_ip_logs(&self, notary_id: u8, ipbits: [u8; 4], now: u32) {
+        (**self).update_ip_logs(notary_id, ipbits, now);
+    }
+
+    fn update_known_ips(&self, notary_id: u8, ips: Vec<[u8; 4]>, now: u32) -> Vec<String> {
+        (**self).update_known_ips(notary_id, ips, now)
+    }
+
+    fn known_ips(&self) -> Vec<String> {
+        (**self).known_ips()
+    }
+
+    fn banned_ips(&self) -> Vec<String> {
+        (**self).banned_ips()
+    }
+
+    fn ban_ip(&self, ip: &str) {
+        (**self).ban_ip(ip);
+    }
+
+    fn unban_ip(&self, ip: &str) {
+        (**self).unban_ip(ip);
+    }
+
+    fn record_incident(&self, incident: &SpamIncident) {
+        (**self).record_incident(incident);
+    }
+
+    fn save_round_change(&self, change: &RoundChange) {
+        (**self).save_round_change(change);
+    }
+
+    fn update_notary_height(
+        &self,
+        symbol: &str,
+        notary_id: u8,
+        height: u32,
+        srchash: [u8; 32],
+        now: u32,
+    ) {
+        (**self).update_notary_height(symbol, notary_id, height, srchash, now);
+    }
+
+    fn save_utxo(&self, utxo: &NotaryUtxo) {
+        (**self).save_utxo(utxo);
+    }
+}
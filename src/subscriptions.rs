@@ -0,0 +1,162 @@
+use crate::config::{RpcConfig, WsConfig};
+use crate::message::DecodedMessage;
+use crate::rpc::request_role;
+use futures::future;
+use jsonrpc_core::types::error::Error;
+use jsonrpc_core::types::params::Params;
+use jsonrpc_core::types::Value;
+use jsonrpc_core::MetaIoHandler;
+use jsonrpc_http_server::hyper::header::HeaderValue;
+use jsonrpc_pubsub::{PubSubHandler, Session, Sink, Subscriber, SubscriptionId};
+use jsonrpc_ws_server::ws;
+use jsonrpc_ws_server::{DomainsValidation, Origin, RequestContext, Server, ServerBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// every field is optional; a message must match all fields that are set
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MessageFilter {
+    pub symbol: Option<String>,
+    pub channel: Option<u32>,
+    // notary name, eg "alright_EU"
+    pub notary: Option<String>,
+}
+
+impl MessageFilter {
+    pub fn matches(&self, msg: &DecodedMessage) -> bool {
+        self.symbol.iter().all(|symbol| *symbol == msg.symbol)
+            && self.channel.iter().all(|channel| *channel == msg.channel)
+            && self.notary.iter().all(|notary| *notary == msg.sender)
+    }
+}
+
+// identifies the ws connection a subscription belongs to. the session itself isn't kept, as
+// holding it would stop it being dropped and its subscriptions removed when it closes
+fn session_key(session: &Arc<Session>) -> usize {
+    Arc::as_ptr(session) as usize
+}
+
+// by subscription id, with the session_key of the connection that subscribed
+type Sinks = HashMap<u64, (usize, MessageFilter, Sink)>;
+
+// live message subscribers, shared between the ws server and the listener thread
+#[derive(Clone, Default)]
+pub struct Subscriptions {
+    next_id: Arc<AtomicU64>,
+    sinks: Arc<Mutex<Sinks>>,
+}
+
+impl Subscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.lock().unwrap().is_empty()
+    }
+
+    fn subscribe(&self, filter: MessageFilter, session: &Arc<Session>, subscriber: Subscriber) {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        if let Ok(sink) = subscriber.assign_id(SubscriptionId::Number(id)) {
+            let key = session_key(session);
+            self.sinks.lock().unwrap().insert(id, (key, filter, sink));
+            let sinks = self.sinks.clone();
+            session.on_drop(move || {
+                sinks.lock().unwrap().remove(&id);
+            });
+        }
+    }
+
+    // only the session that subscribed can cancel a subscription
+    fn unsubscribe(&self, id: &SubscriptionId, session: Option<&Arc<Session>>) -> bool {
+        let (id, key) = match (id, session) {
+            (SubscriptionId::Number(id), Some(session)) => (id, session_key(session)),
+            _ => return false,
+        };
+        let mut sinks = self.sinks.lock().unwrap();
+        match sinks.get(id) {
+            Some((owner, _, _)) if *owner == key => sinks.remove(id).is_some(),
+            _ => false,
+        }
+    }
+
+    // send a message to every subscriber whose filter matches it
+    // subscribers are removed when their session closes, or here if sending fails first
+    pub fn publish(&self, msg: &DecodedMessage) {
+        let mut sinks = self.sinks.lock().unwrap();
+        if sinks.is_empty() {
+            return;
+        }
+        let value = serde_json::to_value(msg).unwrap();
+        sinks.retain(|_, (_, filter, sink)| {
+            !filter.matches(msg) || sink.notify(Params::Array(vec![value.clone()])).is_ok()
+        });
+    }
+}
+
+// subscribe with {"symbol": "MARTY", "channel": 0, "notary": "alright_EU"}, all optional
+pub fn messages_pubsub_handler(subscriptions: Subscriptions) -> PubSubHandler<Arc<Session>> {
+    let mut io = PubSubHandler::new(MetaIoHandler::default());
+    let subscriptions_unsubscribe = subscriptions.clone();
+    io.add_subscription(
+        "message",
+        (
+            "subscribe_messages",
+            move |params: Params, session: Arc<Session>, subscriber: Subscriber| {
+                let filter = match params {
+                    Params::None => Ok(MessageFilter::default()),
+                    Params::Map(map) => serde_json::from_value(Value::Object(map))
+                        .map_err(|err| Error::invalid_params(err.to_string())),
+                    _ => Err(Error::invalid_params("Expected map")),
+                };
+                match filter {
+                    Ok(filter) => subscriptions.subscribe(filter, &session, subscriber),
+                    Err(err) => {
+                        let _ = subscriber.reject(err);
+                    }
+                }
+            },
+        ),
+        (
+            "unsubscribe_messages",
+            move |id: SubscriptionId, session: Option<Arc<Session>>| {
+                let removed = subscriptions_unsubscribe.unsubscribe(&id, session.as_ref());
+                future::ok(Value::Bool(removed))
+            },
+        ),
+    );
+    io
+}
+
+// the stream carries everything the read-only RPC methods do, so connections need the same
+// read credentials in their Authorization header
+pub fn start_ws_server(
+    subscriptions: Subscriptions,
+    config: &WsConfig,
+    rpc_config: &RpcConfig,
+) -> Result<Server, String> {
+    let addr: SocketAddr = config.bind.parse().map_err(|err| format!("{}", err))?;
+    let rpc_config = rpc_config.clone();
+    let mut builder = ServerBuilder::with_meta_extractor(
+        messages_pubsub_handler(subscriptions),
+        |context: &RequestContext| Arc::new(Session::new(context.sender())),
+    )
+    .request_middleware(move |request: &ws::Request| {
+        let header = request
+            .header("authorization")
+            .and_then(|value| HeaderValue::from_bytes(value).ok());
+        match request_role(&rpc_config, header.as_ref()) {
+            Some(_) => None,
+            None => Some(ws::Response::new(401, "Unauthorized", vec![])),
+        }
+    });
+    if let Some(origins) = &config.allowed_origins {
+        let origins = origins.iter().map(Origin::from).collect();
+        builder = builder.allowed_origins(DomainsValidation::AllowOnly(origins));
+    }
+    builder.start(&addr).map_err(|err| format!("{}", err))
+}
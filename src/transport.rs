@@ -0,0 +1,265 @@
+use crate::capture::{CaptureReader, CapturedMessage};
+use crate::config::TransportKind;
+use crate::sp::SpBus;
+use nanomsg::{Endpoint, Protocol, Socket};
+use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read, Write};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+// a bus socket the listener reads iguana messages from
+// addresses are nanomsg style urls, eg tcp://1.2.3.4:7775
+pub trait Transport: Send {
+    // the next message, or None if nothing arrived within the receive timeout
+    // an UnexpectedEof error means there will be no more messages, eg a replay has finished
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>>;
+    // sends a message to every connected peer
+    fn send(&mut self, data: &[u8]) -> io::Result<()>;
+    fn connect(&mut self, addr: &str) -> io::Result<()>;
+    fn disconnect(&mut self, addr: &str) -> io::Result<()>;
+
+    // receive time in microseconds of the last message, for transports that know better
+    // than the clock; None means it was received just now
+    fn last_received(&self) -> Option<u64> {
+        None
+    }
+}
+
+fn closed() -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, "transport closed")
+}
+
+// Protocol::Bus socket from the C libnanomsg
+pub struct NanomsgBus {
+    socket: Socket,
+    // bind endpoints are never shut down, only kept alive with the socket
+    binds: Vec<Endpoint>,
+    endpoints: HashMap<String, Endpoint>,
+}
+
+impl NanomsgBus {
+    pub fn new() -> io::Result<Self> {
+        Ok(NanomsgBus {
+            socket: Socket::new(Protocol::Bus)?,
+            binds: vec![],
+            endpoints: HashMap::new(),
+        })
+    }
+
+    pub fn bind(&mut self, addr: &str) -> io::Result<()> {
+        self.binds.push(self.socket.bind(addr)?);
+        Ok(())
+    }
+
+    pub fn set_receive_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.socket
+            .set_receive_timeout(timeout.as_millis() as isize)?;
+        Ok(())
+    }
+
+    // NN_RCVMAXSIZE, larger messages are dropped by libnanomsg before they are read
+    pub fn set_receive_max_size(&mut self, size: usize) -> io::Result<()> {
+        self.socket.set_receive_max_size(size as isize)?;
+        Ok(())
+    }
+}
+
+impl Transport for NanomsgBus {
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut buffer = vec![];
+        match self.socket.read_to_end(&mut buffer) {
+            Ok(_) => Ok(Some(buffer)),
+            Err(err) if matches!(err.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        self.socket.write_all(data)
+    }
+
+    fn connect(&mut self, addr: &str) -> io::Result<()> {
+        if !self.endpoints.contains_key(addr) {
+            let endpoint = self.socket.connect(addr)?;
+            self.endpoints.insert(addr.to_string(), endpoint);
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self, addr: &str) -> io::Result<()> {
+        match self.endpoints.remove(addr) {
+            Some(endpoint) => Ok(endpoint.shutdown()?),
+            None => Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("not connected to {}", addr),
+            )),
+        }
+    }
+}
+
+// a bus socket of the configured kind, optionally bound to a nanomsg style url
+pub fn open_bus(
+    kind: TransportKind,
+    bind: Option<&str>,
+    receive_timeout: Duration,
+    receive_max_size: usize,
+) -> io::Result<Box<dyn Transport>> {
+    match kind {
+        TransportKind::Nanomsg => {
+            let mut bus = NanomsgBus::new()?;
+            bus.set_receive_max_size(receive_max_size)?;
+            if let Some(url) = bind {
+                bus.bind(url)?;
+            }
+            bus.set_receive_timeout(receive_timeout)?;
+            Ok(Box::new(bus))
+        }
+        TransportKind::Native => {
+            let mut bus = SpBus::new();
+            bus.set_receive_max_size(receive_max_size);
+            if let Some(url) = bind {
+                bus.bind(url)?;
+            }
+            bus.set_receive_timeout(Some(receive_timeout));
+            Ok(Box::new(bus))
+        }
+    }
+}
+
+// in-process transport for tests and embedding: messages are injected and sent ones
+// collected through the MemoryHandle returned with it
+pub struct MemoryTransport {
+    incoming: Receiver<Vec<u8>>,
+    outgoing: Sender<Vec<u8>>,
+    receive_timeout: Duration,
+    connected: BTreeSet<String>,
+}
+
+pub struct MemoryHandle {
+    // messages for the transport to receive; dropping it closes the transport
+    pub incoming: Sender<Vec<u8>>,
+    // messages the transport sent
+    pub outgoing: Receiver<Vec<u8>>,
+}
+
+impl MemoryTransport {
+    pub fn new(receive_timeout: Duration) -> (Self, MemoryHandle) {
+        let (incoming_sender, incoming) = channel();
+        let (outgoing, outgoing_receiver) = channel();
+        (
+            MemoryTransport {
+                incoming,
+                outgoing,
+                receive_timeout,
+                connected: BTreeSet::new(),
+            },
+            MemoryHandle {
+                incoming: incoming_sender,
+                outgoing: outgoing_receiver,
+            },
+        )
+    }
+
+    // addresses currently connected to
+    pub fn connected(&self) -> Vec<String> {
+        self.connected.iter().cloned().collect()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        match self.incoming.recv_timeout(self.receive_timeout) {
+            Ok(message) => Ok(Some(message)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(closed()),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        // nobody listening is the same as no peers on a bus
+        let _ = self.outgoing.send(data.to_vec());
+        Ok(())
+    }
+
+    fn connect(&mut self, addr: &str) -> io::Result<()> {
+        self.connected.insert(addr.to_string());
+        Ok(())
+    }
+
+    fn disconnect(&mut self, addr: &str) -> io::Result<()> {
+        if self.connected.remove(addr) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                ErrorKind::NotFound,
+                format!("not connected to {}", addr),
+            ))
+        }
+    }
+}
+
+// receives the messages of a capture file, optionally with their original spacing
+// there are no peers, so sends are dropped and connects succeed without doing anything
+pub struct ReplayTransport<R: Read> {
+    reader: CaptureReader<R>,
+    realtime: bool,
+    last_received: Option<u64>,
+}
+
+impl ReplayTransport<BufReader<File>> {
+    pub fn open(path: &str, realtime: bool) -> io::Result<Self> {
+        Ok(ReplayTransport::new(CaptureReader::open(path)?, realtime))
+    }
+}
+
+impl<R: Read> ReplayTransport<R> {
+    pub fn new(reader: CaptureReader<R>, realtime: bool) -> Self {
+        ReplayTransport {
+            reader,
+            realtime,
+            last_received: None,
+        }
+    }
+}
+
+impl<R: Read + Send> Transport for ReplayTransport<R> {
+    fn recv(&mut self) -> io::Result<Option<Vec<u8>>> {
+        // only the end of the file closes the transport, a truncated or corrupt record is
+        // InvalidData so the replay reports it instead of finishing cleanly
+        let CapturedMessage { received, data } = match self.reader.next() {
+            Some(Ok(record)) => record,
+            Some(Err(err)) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("bad capture record: {}", err),
+                ))
+            }
+            None => return Err(closed()),
+        };
+        if let (true, Some(previous)) = (self.realtime, self.last_received) {
+            thread::sleep(Duration::from_micros(received.saturating_sub(previous)));
+        }
+        self.last_received = Some(received);
+        Ok(Some(data))
+    }
+
+    fn send(&mut self, _data: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn connect(&mut self, _addr: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn disconnect(&mut self, _addr: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn last_received(&self) -> Option<u64> {
+        self.last_received
+    }
+}
@@ -0,0 +1,150 @@
+use crate::db::{NotaryUtxo, UtxoUse};
+use crate::{DpowNanoMsgHdr, DpowNanoUtxo, FIRST_PARTY};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+// offers are forgotten after this long, and a UTXO offered again after it is not reported
+pub const DEFAULT_UTXO_MAX_AGE: u32 = 86400;
+
+// (txid, vout)
+type Outpoint = ([u8; 32], u16);
+type OfferKey = (String, u32, u8, &'static str, &'static str);
+
+// the UTXOs each notary offers for the notarisation tx of a round, srcutxo on the
+// symbol's chain and destutxo on the chain it notarises to. a notary without split
+// UTXOs left sends zeroes, which is reported for the notarize phase; ratify UTXOs are
+// only there while ratifying
+pub struct UtxoTracker {
+    max_age: u32,
+    // the UTXO last offered for each (symbol, height, senderind, phase, kind)
+    offers: HashMap<OfferKey, (Option<Outpoint>, u32)>,
+    // the round each UTXO was last offered in
+    offered: HashMap<Outpoint, (UtxoUse, u32)>,
+    // keys of both maps in the order they were set, to expire the oldest first
+    offers_order: VecDeque<(OfferKey, u32)>,
+    offered_order: VecDeque<(Outpoint, u32)>,
+}
+
+// drops the entries set more than max_age before now, unless they were set again since
+fn expire<K: Eq + Hash, V>(
+    map: &mut HashMap<K, (V, u32)>,
+    order: &mut VecDeque<(K, u32)>,
+    now: u32,
+    max_age: u32,
+) {
+    while let Some((key, time)) = order.front() {
+        if now.saturating_sub(*time) < max_age {
+            break;
+        }
+        if map.get(key).map(|(_, set)| set) == Some(time) {
+            map.remove(key);
+        }
+        order.pop_front();
+    }
+}
+
+impl Default for UtxoTracker {
+    fn default() -> Self {
+        UtxoTracker::new(DEFAULT_UTXO_MAX_AGE)
+    }
+}
+
+impl UtxoTracker {
+    pub fn new(max_age: u32) -> Self {
+        UtxoTracker {
+            max_age,
+            offers: HashMap::new(),
+            offered: HashMap::new(),
+            offers_order: VecDeque::new(),
+            offered_order: VecDeque::new(),
+        }
+    }
+
+    // the offers that changed with a message received at `now`, for the caller to persist
+    pub fn update_at(&mut self, dpow_msg: &DpowNanoMsgHdr, now: u32) -> Vec<NotaryUtxo> {
+        let mut changes = vec![];
+        let symbol = dpow_msg.symbol_str();
+        if symbol.is_empty() || dpow_msg.senderind as usize >= FIRST_PARTY.len() {
+            return changes;
+        }
+        expire(&mut self.offers, &mut self.offers_order, now, self.max_age);
+        expire(
+            &mut self.offered,
+            &mut self.offered_order,
+            now,
+            self.max_age,
+        );
+
+        for (phase, utxo) in [
+            ("notarize", &dpow_msg.notarize),
+            ("ratify", &dpow_msg.ratify),
+        ] {
+            for (kind, utxo) in offered_utxos(utxo) {
+                if utxo.is_none() && phase == "ratify" {
+                    continue;
+                }
+                let key = (
+                    symbol.clone(),
+                    dpow_msg.height,
+                    dpow_msg.senderind,
+                    phase,
+                    kind,
+                );
+                if self.offers.get(&key).map(|offer| offer.0) == Some(utxo) {
+                    continue;
+                }
+                self.offers.insert(key.clone(), (utxo, now));
+                self.offers_order.push_back((key, now));
+                changes.push(self.offer(&symbol, dpow_msg, phase, kind, utxo, now));
+            }
+        }
+        changes
+    }
+
+    fn offer(
+        &mut self,
+        symbol: &str,
+        dpow_msg: &DpowNanoMsgHdr,
+        phase: &str,
+        kind: &str,
+        utxo: Option<Outpoint>,
+        now: u32,
+    ) -> NotaryUtxo {
+        let current = UtxoUse {
+            symbol: symbol.to_string(),
+            height: dpow_msg.height,
+            senderind: dpow_msg.senderind,
+        };
+        let mut previous = None;
+        if let Some(utxo) = utxo {
+            self.offered_order.push_back((utxo, now));
+            if let Some((used, time)) = self.offered.insert(utxo, (current.clone(), now)) {
+                // a notary offers the same UTXO every round until it is spent, it is only
+                // a problem if another notary offered it too
+                if used.senderind != current.senderind && now.saturating_sub(time) < self.max_age {
+                    previous = Some(used);
+                }
+            }
+        }
+        NotaryUtxo {
+            symbol: current.symbol,
+            height: current.height,
+            senderind: current.senderind,
+            phase: phase.to_string(),
+            kind: kind.to_string(),
+            txid: utxo.map(|(txid, _)| hex::encode(txid)),
+            vout: utxo.map(|(_, vout)| vout),
+            time: now,
+            previous,
+        }
+    }
+}
+
+// the src and dest (txid, vout) of a phase, None where the notary sent zeroes
+fn offered_utxos(utxo: &DpowNanoUtxo) -> [(&'static str, Option<Outpoint>); 2] {
+    let offered = |txid: [u8; 32], vout: u16| (txid != [0; 32]).then_some((txid, vout));
+    [
+        ("src", offered(utxo.srcutxo, utxo.srcvout)),
+        ("dest", offered(utxo.destutxo, utxo.destvout)),
+    ]
+}
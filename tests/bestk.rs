@@ -0,0 +1,77 @@
+mod common;
+
+use common::symbol_msg;
+use iguana_rs::bestk::{check_bestk, dpow_bestk, dpow_maskmin, dpow_modind, BestkMismatch};
+use iguana_rs::rounds::{Rounds, BESTK_NONE};
+
+#[test]
+fn test_dpow_bestk() {
+    // the offset moves on one notary every DPOW_CHECKPOINTFREQ blocks
+    assert_eq!(dpow_modind(97608, 64, 0), 32);
+    assert_eq!(dpow_modind(97610, 64, 0), 33);
+    assert_eq!(dpow_modind(97608, 64, 40), 8);
+
+    assert_eq!(dpow_bestk(u64::MAX, 97608, 64, 13), (44, 0x1fff << 32));
+    let recvmask = 0b0110_1011;
+    assert_eq!(dpow_bestk(recvmask, 25, 8, 3), (6, 0b0110_1000));
+    assert_eq!(
+        dpow_maskmin(recvmask, 25, 8, 3, true),
+        Some((5, 0b0010_1001))
+    );
+    assert_eq!(dpow_bestk(recvmask, 25, 8, 6), (BESTK_NONE, 0));
+
+    // as reported in the captured packet in tests/serde.rs, heard from one notary only
+    let mut msg = symbol_msg("", 1, 97608);
+    msg.notarize.recvmask = 2u64.to_le_bytes();
+    msg.notarize.bestk = BESTK_NONE;
+    assert_eq!(check_bestk(&msg, 64, 13, None), Ok(()));
+
+    msg.notarize.recvmask = u64::MAX.to_le_bytes();
+    msg.notarize.bestk = 32;
+    msg.notarize.bestmask = (0x1fffu64 << 32).to_le_bytes();
+    assert_eq!(
+        check_bestk(&msg, 64, 13, None),
+        Err(BestkMismatch {
+            reported: (32, 0x1fff << 32),
+            expected: (44, 0x1fff << 32),
+        })
+    );
+}
+
+#[test]
+fn test_bestk_consensus_adopted() {
+    // notaries 0 and 1 heard from 0-3 and agree on 1 and 2, starting at 25 / 10 % 4 = 2
+    let mut rounds = Rounds::new();
+    for senderind in 0..2 {
+        let mut msg = symbol_msg("MARTY", senderind, 25);
+        msg.notarize.recvmask = 0b1111u64.to_le_bytes();
+        msg.notarize.bestk = 3;
+        msg.notarize.bestmask = 0b1100u64.to_le_bytes();
+        assert_eq!(check_bestk(&msg, 4, 2, None), Ok(()));
+        rounds.update_at(&msg, 1000);
+    }
+
+    // notary 2 hasn't heard from 3, its own pick would be 2 and 0, but it has adopted theirs
+    let mut msg = symbol_msg("MARTY", 2, 25);
+    msg.notarize.recvmask = 0b0111u64.to_le_bytes();
+    msg.notarize.bestk = 3;
+    msg.notarize.bestmask = 0b1100u64.to_le_bytes();
+    let round = rounds.get("MARTY").unwrap();
+    let adopted = round
+        .consensus_of_others(2)
+        .map(|(bestk, bestmask, _)| (bestk, bestmask));
+    assert_eq!(adopted, Some((3, 0b1100)));
+    assert_eq!(check_bestk(&msg, 4, 2, adopted), Ok(()));
+    assert_eq!(
+        check_bestk(&msg, 4, 2, None),
+        Err(BestkMismatch {
+            reported: (3, 0b1100),
+            expected: (0, 0b0101),
+        })
+    );
+    // the sender's own report isn't counted towards what it could have adopted
+    assert_eq!(
+        round.consensus_of_others(0).map(|(_, _, votes)| votes),
+        Some(1)
+    );
+}
@@ -0,0 +1,49 @@
+use iguana_rs::broadcast::Broadcaster;
+use iguana_rs::config::BroadcastConfig;
+use iguana_rs::packet::decode_packet;
+use iguana_rs::simulator::test_secret_key;
+use iguana_rs::transport::MemoryTransport;
+use secp256k1::{PublicKey, Secp256k1};
+use std::time::Duration;
+
+fn config(secret_key: &str) -> BroadcastConfig {
+    BroadcastConfig {
+        secret_key: secret_key.to_string(),
+        senderind: 3,
+        symbol: "KMD".to_string(),
+        interval_secs: 3600,
+    }
+}
+
+#[test]
+fn test_broadcast_packet() {
+    let sk = test_secret_key(3);
+    let mut broadcaster =
+        Broadcaster::new(&config(&hex::encode(sk.secret_bytes())), "1.2.3.4").unwrap();
+    let peers = vec!["5.6.7.8".to_string(), "1.2.3.4".to_string()];
+
+    let data = broadcaster.packet(&peers);
+    let (packet, rest) = decode_packet(&data).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(
+        packet.pubkey,
+        PublicKey::from_secret_key(&Secp256k1::new(), &sk)
+    );
+    let msg = packet.dpow_msg;
+    assert_eq!(msg.symbol_str(), "KMD");
+    assert_eq!(msg.senderind, 3);
+    assert_eq!(msg.myipbits, [1, 2, 3, 4]);
+    assert_eq!(msg.numipbits, 2);
+    assert_eq!(msg.ipbits[..2], [[1, 2, 3, 4], [5, 6, 7, 8]]);
+
+    // sent straight away, then not again until the interval has passed
+    let (mut transport, handle) = MemoryTransport::new(Duration::from_millis(10));
+    assert!(broadcaster.poll(&mut transport, &peers).unwrap());
+    assert!(!broadcaster.poll(&mut transport, &peers).unwrap());
+    let sent = handle.outgoing.try_recv().unwrap();
+    assert_eq!(decode_packet(&sent).unwrap().0.dpow_msg.senderind, 3);
+    assert!(handle.outgoing.try_recv().is_err());
+
+    assert!(Broadcaster::new(&config("00"), "1.2.3.4").is_err());
+    assert!(Broadcaster::new(&config(&hex::encode(sk.secret_bytes())), "localhost").is_err());
+}
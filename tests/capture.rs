@@ -0,0 +1,68 @@
+use iguana_rs::capture::{CaptureReader, CaptureWriter, CapturedMessage, CAPTURE_MAGIC};
+use iguana_rs::config::CaptureConfig;
+use std::fs;
+use std::io::ErrorKind;
+
+#[test]
+fn test_capture_rotation() {
+    let dir = std::env::temp_dir().join(format!("iguana_capture_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("iguana.cap").to_str().unwrap().to_string();
+
+    let config = CaptureConfig {
+        path: path.clone(),
+        max_bytes: None,
+        max_age_secs: Some(60),
+    };
+    let mut writer = CaptureWriter::new(&config).unwrap();
+    writer.write(1_000_000_000, &[1, 2, 3]).unwrap();
+    writer.write(1_030_000_000, &[4]).unwrap();
+    // a minute after the first record, so this one starts a new file
+    writer.write(1_060_000_000, &[5, 6]).unwrap();
+
+    let rotated: Vec<CapturedMessage> = CaptureReader::open(&format!("{}.1000", path))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        rotated,
+        vec![
+            CapturedMessage {
+                received: 1_000_000_000,
+                data: vec![1, 2, 3]
+            },
+            CapturedMessage {
+                received: 1_030_000_000,
+                data: vec![4]
+            },
+        ]
+    );
+    let current: Vec<CapturedMessage> = CaptureReader::open(&path)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0].data, vec![5, 6]);
+
+    // a truncated final record is an error rather than a silent end of file
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.pop();
+    let mut reader = CaptureReader::new(&bytes[..]).unwrap();
+    assert!(reader.next().unwrap().is_err());
+    assert!(reader.next().is_none());
+
+    // a corrupt length is an error, not a huge allocation
+    let mut bytes = CAPTURE_MAGIC.to_vec();
+    bytes.extend_from_slice(&1u64.to_le_bytes());
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    bytes.extend_from_slice(&[0; 64]);
+    let mut reader = CaptureReader::new(&bytes[..]).unwrap();
+    assert_eq!(
+        reader.next().unwrap().unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    assert!(reader.next().is_none());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,24 @@
+#![allow(dead_code)]
+
+use bincode::Options;
+use iguana_rs::packet::encode_packet;
+use iguana_rs::DpowNanoMsgHdr;
+use secp256k1::SecretKey;
+
+pub fn zeroed_msg() -> DpowNanoMsgHdr {
+    let binconf = bincode::DefaultOptions::new().with_fixint_encoding();
+    let msg_size = std::mem::size_of::<DpowNanoMsgHdr>() - 1;
+    binconf.deserialize(&vec![0u8; msg_size]).unwrap()
+}
+
+pub fn symbol_msg(symbol: &str, senderind: u8, height: u32) -> DpowNanoMsgHdr {
+    let mut msg = zeroed_msg();
+    msg.symbol[..symbol.len()].copy_from_slice(symbol.as_bytes());
+    msg.senderind = senderind;
+    msg.height = height;
+    msg
+}
+
+pub fn signed_packet(msg: &DpowNanoMsgHdr, extra: &[u8], sk: &SecretKey) -> Vec<u8> {
+    encode_packet(msg, extra, sk).unwrap()
+}
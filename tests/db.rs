@@ -0,0 +1,78 @@
+use iguana_rs::db::{
+    get_chain_tip, get_chain_tips, get_ip_history, get_known_ips, get_notaries, get_notary_by_id,
+    get_notary_by_name, init_db, update_ip_logs, update_known_ips, update_lastseen,
+    update_notary_height,
+};
+use rusqlite::Connection;
+
+#[test]
+fn test_notary_queries() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+
+    update_lastseen(&conn, 8);
+    update_ip_logs(&conn, 8, [1, 2, 3, 4]);
+    update_known_ips(&conn, 8, vec![[1, 2, 3, 4], [5, 6, 7, 8], [0; 4]]);
+    update_known_ips(&conn, 9, vec![[5, 6, 7, 8]]);
+
+    let notaries = get_notaries(&conn);
+    assert_eq!(notaries.len(), 64);
+    assert_eq!(notaries[8].name, "alright_EU");
+    assert_eq!(notaries[8].ip, Some("1.2.3.4".to_string()));
+    assert!(notaries[8].lastseen > 0);
+    assert_eq!(notaries[9].ip, None);
+
+    assert_eq!(
+        get_notary_by_name(&conn, "alright_EU"),
+        get_notary_by_id(&conn, 8)
+    );
+    assert_eq!(get_notary_by_name(&conn, "nobody"), None);
+
+    let history = get_ip_history(&conn, 8);
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].ip, "1.2.3.4");
+
+    let known_ips = get_known_ips(&conn);
+    assert_eq!(known_ips.len(), 2);
+    assert_eq!(known_ips[0].ip, "1.2.3.4");
+    assert_eq!(known_ips[0].reported_by, vec!["alright_EU"]);
+    assert_eq!(known_ips[1].reported_by.len(), 2);
+}
+
+#[test]
+fn test_chain_tips() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    assert_eq!(get_chain_tip(&conn, "KMD"), None);
+
+    update_notary_height(&conn, "KMD", 0, 100, [1; 32], 10);
+    update_notary_height(&conn, "KMD", 1, 120, [2; 32], 10);
+    update_notary_height(&conn, "KMD", 2, 120, [2; 32], 11);
+    update_notary_height(&conn, "KMD", 3, 120, [3; 32], 11);
+    update_notary_height(&conn, "KMD", 4, 90, [4; 32], 12);
+    // a later message replaces the notary's height, but never with a lower one
+    update_notary_height(&conn, "KMD", 4, 110, [5; 32], 13);
+    update_notary_height(&conn, "KMD", 4, 95, [4; 32], 14);
+    // one notary far ahead doesn't move the tip
+    update_notary_height(&conn, "KMD", 5, 999_999, [7; 32], 14);
+    update_notary_height(&conn, "LTC", 0, 5, [6; 32], 14);
+
+    let tip = get_chain_tip(&conn, "KMD").unwrap();
+    assert_eq!(tip.height, 120);
+    assert_eq!(tip.srchash, hex::encode([2; 32]));
+    assert_eq!(tip.agreeing, 2);
+    assert_eq!(tip.notaries, 6);
+    assert_eq!(tip.ahead, vec!["alien_EU"]);
+    let lagging: Vec<(u32, u32)> = tip
+        .lagging
+        .iter()
+        .map(|lag| (lag.height, lag.behind))
+        .collect();
+    assert_eq!(lagging, vec![(100, 20), (110, 10)]);
+    assert_eq!(tip.lagging[1].updated, 13);
+
+    let tips = get_chain_tips(&conn);
+    assert_eq!(tips.len(), 2);
+    assert_eq!(tips[1].symbol, "LTC");
+    assert!(tips[1].lagging.is_empty());
+}
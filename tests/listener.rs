@@ -0,0 +1,100 @@
+use iguana_rs::db::{ban_ip, init_db};
+use iguana_rs::listener::{Listener, MessageHandler};
+use iguana_rs::notaries::{Notary, NotarySet};
+use iguana_rs::packet::{Packet, PacketError};
+use iguana_rs::simulator::{test_secret_key, Simulator};
+use iguana_rs::transport::MemoryTransport;
+use rusqlite::Connection;
+use secp256k1::{PublicKey, Secp256k1};
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+struct Recorder {
+    messages: Arc<Mutex<Vec<(u8, u32)>>>,
+    invalid: Arc<Mutex<Vec<PacketError>>>,
+}
+
+impl MessageHandler for Recorder {
+    fn on_message(&self, packet: &Packet, _received: u32) {
+        self.messages
+            .lock()
+            .unwrap()
+            .push((packet.dpow_msg.senderind, packet.dpow_msg.height));
+    }
+
+    fn on_invalid(&self, err: PacketError) {
+        self.invalid.lock().unwrap().push(err);
+    }
+}
+
+#[test]
+fn test_embedded_listener() {
+    let secp = Secp256k1::new();
+    let pubkey = |senderind| PublicKey::from_secret_key(&secp, &test_secret_key(senderind));
+    // notary 1 is expected to sign with notary 2's key, and there is no notary 2
+    let notaries = NotarySet::new(vec![
+        Notary {
+            name: "zero".to_string(),
+            pubkey: Some(pubkey(0)),
+        },
+        Notary {
+            name: "one".to_string(),
+            pubkey: Some(pubkey(2)),
+        },
+    ]);
+
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let (transport, handle) = MemoryTransport::new(Duration::from_millis(10));
+    let recorder = Recorder::default();
+    let listener = Listener::builder()
+        .transport(Box::new(transport))
+        .notaries(notaries)
+        .storage(conn)
+        .handler(Box::new(recorder.clone()))
+        .build()
+        .unwrap();
+    let rounds = listener.rounds();
+
+    let mut simulator = Simulator::new(3, "MARTY", 1000);
+    for packet in simulator.step() {
+        handle.incoming.send(packet).unwrap();
+    }
+    drop(handle);
+    listener.run().unwrap();
+
+    assert_eq!(*recorder.messages.lock().unwrap(), vec![(0, 1000)]);
+    assert_eq!(
+        *recorder.invalid.lock().unwrap(),
+        vec![PacketError::PubkeyMismatch, PacketError::UnknownSender]
+    );
+    assert_eq!(
+        rounds.lock().unwrap().get("MARTY").unwrap().notaries.len(),
+        1
+    );
+
+    assert!(Listener::builder::<Connection>()
+        .transport(Box::new(MemoryTransport::new(Duration::from_millis(10)).0))
+        .build()
+        .is_err());
+}
+
+#[test]
+fn test_bootstrap_connect_error() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    ban_ip(&conn, "10.0.0.1");
+    let (transport, _handle) = MemoryTransport::new(Duration::from_millis(10));
+    let listener = Listener::builder()
+        .transport(Box::new(transport))
+        .storage(conn)
+        .peer("10.0.0.1")
+        .build()
+        .unwrap();
+
+    let err = listener.run().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::NotConnected);
+    assert!(err.to_string().contains("10.0.0.1"));
+}
@@ -0,0 +1,69 @@
+use iguana_rs::config::{Config, LogFormat};
+use iguana_rs::logging::Logger;
+use log::{Level, LevelFilter, Log, Record};
+
+fn logger(config: &str) -> Logger {
+    let config: Config = serde_json::from_str(config).unwrap();
+    Logger::new(&config.logging).unwrap()
+}
+
+#[test]
+fn test_module_filters() {
+    let logger = logger(
+        r#"{"logging": {"level": "warn", "filters": {"iguana_rs": "info", "iguana_rs::peers": "debug"}}}"#,
+    );
+    assert_eq!(logger.max_level(), LevelFilter::Debug);
+
+    let enabled = |target: &str, level: Level| {
+        logger.enabled(&log::Metadata::builder().target(target).level(level).build())
+    };
+    assert!(enabled("iguana_rs::peers", Level::Debug));
+    assert!(enabled("iguana_rs::message", Level::Info));
+    assert!(!enabled("iguana_rs::message", Level::Debug));
+    // prefixes only match whole module path segments
+    assert!(!enabled("iguana_rs_listener", Level::Info));
+    assert!(enabled("iguana_rs_listener", Level::Warn));
+
+    let config: Config = serde_json::from_str(r#"{"logging": {"level": "loud"}}"#).unwrap();
+    assert!(Logger::new(&config.logging).is_err());
+}
+
+#[test]
+fn test_json_format() {
+    let logger = logger(r#"{"logging": {"format": "json"}}"#);
+    let kvs: &[(&str, &dyn log::kv::ToValue)] = &[
+        ("symbol", &"MARTY"),
+        ("height", &1000u32),
+        ("bestk", &-1i16),
+    ];
+    let line = logger.format(
+        &Record::builder()
+            .level(Level::Info)
+            .target("iguana_rs::message")
+            .args(format_args!("alright_EU MARTY 1000"))
+            .key_values(&kvs)
+            .build(),
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(json["level"], "INFO");
+    assert_eq!(json["target"], "iguana_rs::message");
+    assert_eq!(json["msg"], "alright_EU MARTY 1000");
+    assert_eq!(json["symbol"], "MARTY");
+    assert_eq!(json["height"], 1000);
+    assert_eq!(json["bestk"], -1);
+
+    let text = Logger::new(&Default::default()).unwrap();
+    assert_eq!(LogFormat::default(), LogFormat::Text);
+    let line = text.format(
+        &Record::builder()
+            .level(Level::Warn)
+            .target("iguana_rs_listener")
+            .args(format_args!("dropping invalid packet"))
+            .key_values(&kvs)
+            .build(),
+    );
+    assert!(line.ends_with(
+        "WARN  iguana_rs_listener: dropping invalid packet symbol=\"MARTY\" height=1000 bestk=-1"
+    ));
+}
@@ -0,0 +1,50 @@
+use iguana_rs::db::{init_db, update_known_ips, update_lastseen};
+use iguana_rs::metrics::{Metrics, MAX_MESSAGE_SYMBOLS};
+use iguana_rs::packet::PacketError;
+use rusqlite::Connection;
+use std::time::Duration;
+
+#[test]
+fn test_metrics_render() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    update_lastseen(&conn, 8);
+    update_known_ips(&conn, 8, vec![[1, 2, 3, 4]]);
+
+    let metrics = Metrics::new();
+    metrics.packet_received();
+    metrics.packet_received();
+    metrics.packet_valid(42);
+    metrics.packet_invalid(PacketError::BadSignature);
+    metrics.message("alright_EU", "MARTY");
+    metrics.db_write(Duration::from_millis(2));
+    metrics.set_connected_peers(3);
+
+    let text = metrics.render(&conn);
+    assert!(text.contains("iguana_packets_received_total 2\n"));
+    assert!(text.contains("iguana_packets_valid_total 1\n"));
+    assert!(text.contains("iguana_packets_invalid_total{reason=\"bad_signature\"} 1\n"));
+    assert!(text.contains("iguana_messages_total{notary=\"alright_EU\",symbol=\"MARTY\"} 1\n"));
+    assert!(text.contains("iguana_notary_last_seen_seconds{notary=\"alright_EU\"} "));
+    assert!(!text.contains("iguana_notary_last_seen_seconds{notary=\"alright_DEV\"}"));
+    assert!(text.contains("iguana_known_ips 1\n"));
+    assert!(text.contains("iguana_connected_peers 3\n"));
+    assert!(text.contains("iguana_packet_nonce_bucket{le=\"10\"} 0\n"));
+    assert!(text.contains("iguana_packet_nonce_bucket{le=\"50\"} 1\n"));
+    assert!(text.contains("iguana_db_write_seconds_count 1\n"));
+}
+
+#[test]
+fn test_metrics_symbols_capped() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let metrics = Metrics::new();
+    for i in 0..MAX_MESSAGE_SYMBOLS + 2 {
+        metrics.message("alright_EU", &format!("SYM{}", i));
+    }
+    metrics.message("alright_EU", "SYM0");
+
+    let text = metrics.render(&conn);
+    assert!(text.contains("iguana_messages_total{notary=\"alright_EU\",symbol=\"SYM0\"} 2\n"));
+    assert!(text.contains("iguana_messages_total{notary=\"alright_EU\",symbol=\"other\"} 2\n"));
+}
@@ -0,0 +1,15 @@
+use iguana_rs::config::NotaryConfig;
+use iguana_rs::notaries::NotarySet;
+
+#[test]
+fn test_notary_set_size() {
+    let notaries: Vec<NotaryConfig> = (0..65)
+        .map(|i| NotaryConfig {
+            name: format!("notary{}", i),
+            pubkey: None,
+        })
+        .collect();
+    // bestmask is a u64, so a notary set can't be larger
+    assert!(NotarySet::from_config(&notaries[..64]).is_ok());
+    assert!(NotarySet::from_config(&notaries).is_err());
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use common::symbol_msg;
+use iguana_rs::notarisation::{
+    assemble_notarisation, notarisation_opret, tx_hex, NotarisationError,
+};
+use iguana_rs::DpowNanoMsgHdr;
+
+fn notary_msg(senderind: u8, bestk: u8, bestmask: u64, siglen: u8) -> DpowNanoMsgHdr {
+    let mut msg = symbol_msg("MARTY", senderind, 100);
+    msg.srchash = [7; 32];
+    msg.notarize.bestk = bestk;
+    msg.notarize.bestmask = bestmask.to_le_bytes();
+    msg.notarize.destutxo = [0x10 + senderind; 32];
+    msg.notarize.destvout = senderind as u16;
+    msg.notarize.sig1 = [senderind; 128];
+    msg.notarize.siglens = [siglen, 0];
+    msg
+}
+
+#[test]
+fn test_assemble_notarisation() {
+    let mut msgs = vec![
+        notary_msg(0, 3, 0b1011, 71),
+        notary_msg(1, 3, 0b1011, 72),
+        // disagrees, and isn't in the agreed bestmask
+        notary_msg(2, 2, 0b0111, 71),
+        // not signed yet
+        notary_msg(3, 3, 0b1011, 0),
+    ];
+
+    let notarisation = assemble_notarisation(&msgs, &[]).unwrap();
+    assert_eq!((notarisation.bestk, notarisation.bestmask), (3, 0b1011));
+    assert_eq!(notarisation.signed, None);
+    assert_eq!(notarisation.txid(), None);
+    let tx = &notarisation.unsigned;
+    let inputs: Vec<(u8, u32)> = tx
+        .inputs
+        .iter()
+        .map(|input| (input.previous_output.hash[0], input.previous_output.index))
+        .collect();
+    assert_eq!(inputs, vec![(0x10, 0), (0x11, 1), (0x13, 3)]);
+    assert!(tx.inputs.iter().all(|input| input.script_sig.is_empty()));
+
+    // 3 inputs of 10000, less the fee
+    assert_eq!(tx.outputs[0].value, 20000);
+    assert_eq!(tx.outputs[0].script_pubkey.len(), 35);
+    let mut opret = vec![0x6a, 42];
+    opret.extend_from_slice(&[7; 32]);
+    opret.extend_from_slice(&100u32.to_le_bytes());
+    opret.extend_from_slice(b"MARTY\0");
+    assert_eq!(tx.outputs[1].value, 0);
+    assert_eq!(tx.outputs[1].script_pubkey.to_vec(), opret);
+    assert!(tx_hex(tx).starts_with("0100000003"));
+
+    // the last signature completes the tx
+    msgs.push(notary_msg(3, 3, 0b1011, 70));
+    let notarisation = assemble_notarisation(&msgs, &[]).unwrap();
+    let signed = notarisation.signed.as_ref().unwrap();
+    assert_eq!(signed.inputs[1].script_sig[0], 72);
+    assert_eq!(
+        signed.inputs[2].script_sig.to_vec(),
+        [&[70], &[3; 70][..]].concat()
+    );
+    assert_eq!(notarisation.txid().unwrap().len(), 64);
+
+    assert_eq!(
+        assemble_notarisation(&[], &[]),
+        Err(NotarisationError::NoMessages)
+    );
+    let mut other_height = notary_msg(1, 3, 0b1011, 72);
+    other_height.height = 110;
+    assert_eq!(
+        assemble_notarisation(&[msgs[0].clone(), other_height], &[]),
+        Err(NotarisationError::MixedRounds)
+    );
+    assert_eq!(
+        assemble_notarisation(&msgs[..2], &[]),
+        Err(NotarisationError::MissingUtxo(3))
+    );
+}
+
+#[test]
+fn test_long_opret() {
+    // 32 + 4 + "MARTY\0" + extras, pushed with OP_PUSHDATA1 up to 255 bytes
+    let opret = notarisation_opret([7; 32], 100, "MARTY", &[1; 213]);
+    assert_eq!(opret[..3], [0x6a, 0x4c, 255]);
+    assert_eq!(opret.len(), 3 + 255);
+
+    let opret = notarisation_opret([7; 32], 100, "MARTY", &[1; 214]);
+    assert_eq!(opret[..4], [0x6a, 0x4d, 0, 1]);
+    assert_eq!(opret.len(), 4 + 256);
+    assert_eq!(opret[4 + 42..], [1; 214]);
+}
@@ -0,0 +1,80 @@
+mod common;
+
+use common::{signed_packet, symbol_msg};
+use iguana_rs::packet::{
+    decode_packet, decode_packet_with_limits, DecodeLimits, PacketError, HEADER_SIZE,
+};
+use secp256k1::{Secp256k1, SecretKey};
+
+#[test]
+fn test_decode_packet() {
+    let sk = SecretKey::from_slice(&[77; 32]).unwrap();
+    let msg = symbol_msg("MARTY", 1, 97608);
+    let mut buffer = signed_packet(&msg, &[1, 2, 3], &sk);
+    let packet_len = buffer.len();
+    buffer.extend(signed_packet(&symbol_msg("KMD", 2, 10), &[], &sk));
+
+    let (packet, rest) = decode_packet(&buffer).unwrap();
+    assert_eq!(packet.pubkey, sk.public_key(&Secp256k1::new()));
+    assert_eq!(packet.dpow_msg.symbol_str(), "MARTY");
+    assert_eq!(packet.dpow_msg.datalen, 3);
+    assert_eq!(packet.extra, vec![1, 2, 3]);
+
+    let (packet, rest) = decode_packet(rest).unwrap();
+    assert_eq!(packet.dpow_msg.symbol_str(), "KMD");
+    assert!(rest.is_empty());
+
+    let single = &buffer[..packet_len];
+    assert_eq!(
+        decode_packet(&single[..HEADER_SIZE - 1]),
+        Err(PacketError::Truncated)
+    );
+    assert_eq!(
+        decode_packet(&single[..packet_len - 1]),
+        Err(PacketError::Truncated)
+    );
+
+    let mut bad_hash = single.to_vec();
+    bad_hash[HEADER_SIZE + 10] ^= 1;
+    assert_eq!(decode_packet(&bad_hash), Err(PacketError::BadPacketHash));
+
+    let mut bad_sig = single.to_vec();
+    bad_sig[..64].copy_from_slice(&[0xff; 64]);
+    assert_eq!(decode_packet(&bad_sig), Err(PacketError::BadSignature));
+}
+
+#[test]
+fn test_decode_limits() {
+    let sk = SecretKey::from_slice(&[77; 32]).unwrap();
+    let buffer = signed_packet(&symbol_msg("MARTY", 1, 97608), &[0; 100], &sk);
+    let limits = DecodeLimits {
+        max_packet_size: buffer.len(),
+        max_datalen: 100,
+    };
+    assert!(decode_packet_with_limits(&buffer, &limits).is_ok());
+    assert_eq!(
+        decode_packet_with_limits(
+            &buffer,
+            &DecodeLimits {
+                max_packet_size: buffer.len() - 1,
+                ..limits
+            }
+        ),
+        Err(PacketError::PacketTooLarge)
+    );
+    assert_eq!(
+        decode_packet_with_limits(
+            &buffer,
+            &DecodeLimits {
+                max_datalen: 99,
+                ..limits
+            }
+        ),
+        Err(PacketError::DatalenTooLarge)
+    );
+
+    // a hostile packetlen is rejected before the buffer is looked at
+    let mut huge = buffer.clone();
+    huge[HEADER_SIZE - 4..HEADER_SIZE].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(decode_packet(&huge), Err(PacketError::PacketTooLarge));
+}
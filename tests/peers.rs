@@ -0,0 +1,64 @@
+use iguana_rs::db::{get_known_ips, init_db, update_known_ips};
+use iguana_rs::peers::{PeerCommand, Peers};
+use iguana_rs::sp::SpBus;
+use rusqlite::Connection;
+
+#[test]
+fn test_peer_bans_persist() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut transport = SpBus::new();
+
+    let mut peers = Peers::new(&conn, "7775");
+    let list = peers
+        .handle(
+            &mut transport,
+            &conn,
+            PeerCommand::Ban("1.2.3.4".to_string()),
+        )
+        .unwrap();
+    assert_eq!(list.banned, vec!["1.2.3.4"]);
+    assert!(list.connected.is_empty());
+
+    // other notaries reporting the banned IP doesn't bring it back as a known IP
+    assert_eq!(
+        update_known_ips(&conn, 8, vec![[1, 2, 3, 4], [5, 6, 7, 8]]),
+        vec!["5.6.7.8"]
+    );
+    assert_eq!(get_known_ips(&conn).len(), 1);
+
+    assert!(peers
+        .handle(
+            &mut transport,
+            &conn,
+            PeerCommand::Add("1.2.3.4".to_string())
+        )
+        .is_err());
+    assert!(peers
+        .handle(
+            &mut transport,
+            &conn,
+            PeerCommand::Add("not an ip".to_string())
+        )
+        .is_err());
+    assert!(peers
+        .handle(
+            &mut transport,
+            &conn,
+            PeerCommand::Remove("5.6.7.8".to_string())
+        )
+        .is_err());
+
+    // a restarted listener picks the ban up from the db
+    let mut peers = Peers::new(&conn, "7775");
+    assert!(peers.is_banned("1.2.3.4"));
+    let list = peers
+        .handle(
+            &mut transport,
+            &conn,
+            PeerCommand::Unban("1.2.3.4".to_string()),
+        )
+        .unwrap();
+    assert!(list.banned.is_empty());
+    assert!(!Peers::new(&conn, "7775").is_banned("1.2.3.4"));
+}
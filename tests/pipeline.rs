@@ -0,0 +1,233 @@
+mod common;
+
+use common::{signed_packet, symbol_msg};
+use iguana_rs::capture::{CaptureReader, CaptureWriter};
+use iguana_rs::config::{CaptureConfig, Config, RateLimitConfig};
+use iguana_rs::db::{get_ip_history, get_notary_by_id, get_spam_incidents, init_db};
+use iguana_rs::dedup::SeenPackets;
+use iguana_rs::metrics::Metrics;
+use iguana_rs::pipeline::Pipeline;
+use iguana_rs::ratelimit::RateLimiter;
+use iguana_rs::rounds::Rounds;
+use iguana_rs::subscriptions::Subscriptions;
+use rusqlite::Connection;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_replay_capture() {
+    let sk = SecretKey::from_slice(&[77; 32]).unwrap();
+    let path = std::env::temp_dir()
+        .join(format!("iguana_replay_{}.cap", std::process::id()))
+        .to_str()
+        .unwrap()
+        .to_string();
+    let _ = std::fs::remove_file(&path);
+
+    let mut first = symbol_msg("MARTY", 8, 1000);
+    first.myipbits = [1, 2, 3, 4];
+    first.ipbits[0] = [5, 6, 7, 8];
+    let mut writer = CaptureWriter::new(&CaptureConfig {
+        path: path.clone(),
+        max_bytes: None,
+        max_age_secs: None,
+    })
+    .unwrap();
+    writer
+        .write(1_600_000_000_000_000, &signed_packet(&first, &[], &sk))
+        .unwrap();
+    // two packets in one message, the second one truncated
+    let mut data = signed_packet(&symbol_msg("MARTY", 9, 1000), &[], &sk);
+    data.extend(&signed_packet(&symbol_msg("KMD", 10, 10), &[], &sk)[..50]);
+    writer.write(1_600_000_060_000_000, &data).unwrap();
+
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let rounds = Arc::new(Mutex::new(Rounds::new()));
+    let pipeline = Pipeline::new(
+        conn,
+        rounds.clone(),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
+    );
+
+    let mut new_ips = vec![];
+    for record in CaptureReader::open(&path).unwrap() {
+        let record = record.unwrap();
+        new_ips.extend(pipeline.process(&record.data, (record.received / 1_000_000) as u32));
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(new_ips, vec!["5.6.7.8"]);
+    // timestamps come from the capture, not the time of the replay
+    assert_eq!(
+        get_notary_by_id(&pipeline.storage, 8).unwrap().lastseen,
+        1_600_000_000
+    );
+    assert_eq!(
+        get_notary_by_id(&pipeline.storage, 9).unwrap().lastseen,
+        1_600_000_060
+    );
+    assert_eq!(get_notary_by_id(&pipeline.storage, 10).unwrap().lastseen, 0);
+    assert_eq!(
+        get_ip_history(&pipeline.storage, 8)[0].first_seen,
+        1_600_000_000
+    );
+
+    let rounds = rounds.lock().unwrap();
+    let round = rounds.get("MARTY").unwrap();
+    assert_eq!(round.height, 1000);
+    assert_eq!(round.notaries.len(), 2);
+    assert_eq!(round.updated, 1_600_000_060);
+    assert!(rounds.get("KMD").is_none());
+
+    let metrics = pipeline.metrics.render(&pipeline.storage);
+    assert!(metrics.contains("iguana_packets_received_total 3"));
+    assert!(metrics.contains("iguana_packets_invalid_total{reason=\"truncated\"} 1"));
+}
+
+#[test]
+fn test_duplicate_packets() {
+    let sk = SecretKey::from_slice(&[77; 32]).unwrap();
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let pipeline = Pipeline::new(
+        conn,
+        Arc::new(Mutex::new(Rounds::new())),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
+    );
+    *pipeline.seen.lock().unwrap() = SeenPackets::new(16).with_max_age(60);
+
+    let packet = signed_packet(&symbol_msg("MARTY", 8, 1000), &[], &sk);
+    pipeline.process(&packet, 1000);
+    // the same packet from another peer only counts as a duplicate
+    pipeline.process(&packet, 1030);
+    assert_eq!(
+        get_notary_by_id(&pipeline.storage, 8).unwrap().lastseen,
+        1000
+    );
+    // until it has been forgotten
+    pipeline.process(&packet, 1100);
+    assert_eq!(
+        get_notary_by_id(&pipeline.storage, 8).unwrap().lastseen,
+        1100
+    );
+
+    let metrics = pipeline.metrics.render(&pipeline.storage);
+    assert!(metrics.contains("iguana_packets_valid_total 2\n"));
+    assert!(metrics.contains("iguana_packets_duplicate_total 1\n"));
+    assert!(metrics.contains("iguana_packets_duplicate_ratio 0.3333"));
+}
+
+#[test]
+fn test_forged_duplicate() {
+    let sk = SecretKey::from_slice(&[77; 32]).unwrap();
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let pipeline = Pipeline::new(
+        conn,
+        Arc::new(Mutex::new(Rounds::new())),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
+    );
+
+    let packet = signed_packet(&symbol_msg("MARTY", 8, 1000), &[], &sk);
+    // same packethash, but not signed by anyone
+    let mut forged = packet.clone();
+    forged[..64].copy_from_slice(&[0xff; 64]);
+    pipeline.process(&forged, 1000);
+    pipeline.process(&packet, 1010);
+    pipeline.process(&forged, 1020);
+    assert_eq!(
+        get_notary_by_id(&pipeline.storage, 8).unwrap().lastseen,
+        1010
+    );
+
+    let metrics = pipeline.metrics.render(&pipeline.storage);
+    assert!(metrics.contains("iguana_packets_valid_total 1\n"));
+    assert!(metrics.contains("iguana_packets_invalid_total{reason=\"bad_signature\"} 1\n"));
+    assert!(metrics.contains("iguana_packets_duplicate_total 1\n"));
+}
+
+#[test]
+fn test_rate_limit() {
+    let sk = SecretKey::from_slice(&[77; 32]).unwrap();
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut pipeline = Pipeline::new(
+        conn,
+        Arc::new(Mutex::new(Rounds::new())),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
+    );
+    pipeline.rate_limiter = Some(Mutex::new(
+        RateLimiter::new(&RateLimitConfig {
+            window_secs: 60,
+            max_per_sender: 2,
+            max_per_pubkey: 3,
+            drop: true,
+        })
+        .unwrap(),
+    ));
+
+    // senderind 8 goes over its limit with the third message, the key with the fourth
+    for height in 0..4 {
+        pipeline.process(
+            &signed_packet(&symbol_msg("MARTY", 8, height), &[], &sk),
+            1000,
+        );
+    }
+    pipeline.process(&signed_packet(&symbol_msg("MARTY", 9, 0), &[], &sk), 1001);
+    // a new window starts over
+    pipeline.process(&signed_packet(&symbol_msg("MARTY", 8, 4), &[], &sk), 1060);
+
+    let incidents = get_spam_incidents(&pipeline.storage, 10);
+    let kinds: Vec<(u8, &str, u32)> = incidents
+        .iter()
+        .map(|incident| (incident.senderind, incident.kind.as_str(), incident.count))
+        .collect();
+    assert_eq!(kinds, vec![(8, "pubkey", 4), (8, "senderind", 3)]);
+    assert_eq!(
+        get_notary_by_id(&pipeline.storage, 8).unwrap().lastseen,
+        1060
+    );
+    assert_eq!(get_notary_by_id(&pipeline.storage, 9).unwrap().lastseen, 0);
+    let metrics = pipeline.metrics.render(&pipeline.storage);
+    assert!(metrics.contains("iguana_packets_invalid_total{reason=\"rate_limited\"} 3\n"));
+}
+
+#[test]
+fn test_rate_limiter_bounds() {
+    let secp = Secp256k1::new();
+    let pubkey = |i: u32| {
+        let mut secret = [1; 32];
+        secret[..4].copy_from_slice(&i.to_be_bytes());
+        PublicKey::from_secret_key(&secp, &SecretKey::from_slice(&secret).unwrap())
+    };
+    let mut limiter = RateLimiter::new(&RateLimitConfig {
+        window_secs: 60,
+        max_per_sender: u32::MAX,
+        max_per_pubkey: 1,
+        drop: true,
+    })
+    .unwrap();
+    assert!(!limiter.check(8, &pubkey(0), 1000).over_limit);
+    assert!(limiter.check(8, &pubkey(0), 1000).drop);
+    // enough other keys push the first out, so its count starts over
+    for i in 1..=4096 {
+        limiter.check(9, &pubkey(i), 1001);
+    }
+    assert!(!limiter.check(8, &pubkey(0), 1002).over_limit);
+    assert!(RateLimiter::new(&RateLimitConfig {
+        window_secs: 0,
+        ..RateLimitConfig::default()
+    })
+    .is_err());
+
+    let path = std::env::temp_dir().join(format!("iguana_config_{}.json", std::process::id()));
+    std::fs::write(&path, r#"{ "rate_limit": { "window_secs": 0 } }"#).unwrap();
+    let loaded = Config::load(path.to_str().unwrap());
+    std::fs::remove_file(&path).unwrap();
+    assert!(loaded.unwrap_err().contains("window_secs"));
+}
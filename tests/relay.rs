@@ -0,0 +1,69 @@
+use iguana_rs::notaries::{Notary, NotarySet};
+use iguana_rs::relay::{Relay, RelayStats};
+use iguana_rs::simulator::{test_secret_key, Fault, Simulator};
+use iguana_rs::transport::MemoryTransport;
+use secp256k1::{PublicKey, Secp256k1};
+use std::time::Duration;
+
+fn simulated_notaries(numnotaries: u8) -> NotarySet {
+    let secp = Secp256k1::new();
+    NotarySet::new(
+        (0..numnotaries)
+            .map(|senderind| Notary {
+                name: format!("notary{}", senderind),
+                pubkey: Some(PublicKey::from_secret_key(
+                    &secp,
+                    &test_secret_key(senderind),
+                )),
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn test_relay_filters_packets() {
+    let (a, a_handle) = MemoryTransport::new(Duration::from_millis(10));
+    let (b, b_handle) = MemoryTransport::new(Duration::from_millis(10));
+    let mut relay = Relay::new(Box::new(a), Box::new(b), simulated_notaries(3)).unwrap();
+
+    let mut simulator = Simulator::new(3, "MARTY", 1000);
+    let packets = simulator.step();
+    // two valid packets in one message are forwarded separately
+    let mut joined = packets[0].clone();
+    joined.extend(&packets[1]);
+    a_handle.incoming.send(joined).unwrap();
+    a_handle.incoming.send(packets[0].clone()).unwrap();
+    a_handle
+        .incoming
+        .send(simulator.faulty_packet(2, Fault::BadSignature))
+        .unwrap();
+    // what was relayed one way isn't echoed back
+    b_handle.incoming.send(packets[1].clone()).unwrap();
+    b_handle.incoming.send(packets[2].clone()).unwrap();
+
+    drop(a_handle.incoming);
+    relay.run().unwrap();
+
+    // sides take turns, so everything queued is handled before side a ends
+    let to_b: Vec<Vec<u8>> = b_handle.outgoing.try_iter().collect();
+    assert_eq!(to_b, packets[..2].to_vec());
+    let to_a: Vec<Vec<u8>> = a_handle.outgoing.try_iter().collect();
+    assert_eq!(to_a, vec![packets[2].clone()]);
+    assert_eq!(
+        relay.stats(),
+        RelayStats {
+            forwarded: 3,
+            invalid: 1,
+            duplicate: 2,
+        }
+    );
+}
+
+#[test]
+fn test_relay_needs_pubkeys() {
+    let transport = || Box::new(MemoryTransport::new(Duration::from_millis(10)).0);
+    let err = Relay::new(transport(), transport(), NotarySet::default())
+        .err()
+        .unwrap();
+    assert!(err.contains("blackice_DEV"));
+}
@@ -0,0 +1,227 @@
+mod common;
+
+use common::symbol_msg;
+use iguana_rs::db::{get_forks, get_round_history, get_round_notaries, init_db};
+use iguana_rs::participation::notary_participation;
+use iguana_rs::rounds::{HashKind, RoundChange, Rounds};
+use iguana_rs::storage::Storage;
+use iguana_rs::DpowNanoMsgHdr;
+use rusqlite::Connection;
+
+fn round_msg(senderind: u8, height: u32, bestk: u8, bestmask: u64) -> DpowNanoMsgHdr {
+    let mut msg = symbol_msg("MARTY", senderind, height);
+    msg.srchash = [height as u8; 32];
+    msg.notarize.bestk = bestk;
+    msg.notarize.bestmask = bestmask.to_le_bytes();
+    msg.notarize.recvmask = 0b1111u64.to_le_bytes();
+    msg
+}
+
+#[test]
+fn test_round_consensus() {
+    let mut rounds = Rounds::new();
+    rounds.update(&round_msg(0, 100, 1, 0b0011));
+    rounds.update(&round_msg(1, 100, 1, 0b0011));
+    rounds.update(&round_msg(2, 100, 2, 0b0111));
+    rounds.update(&round_msg(3, 100, 255, 0));
+
+    let round = rounds.get("MARTY").unwrap();
+    assert_eq!(round.height, 100);
+    assert_eq!(round.notaries.len(), 4);
+    assert_eq!(round.consensus(), Some((1, 0b0011, 2)));
+
+    let summary = round.summary();
+    assert_eq!(
+        summary.consensus_bestmask,
+        Some("0000000000000003".to_string())
+    );
+    assert_eq!(summary.notaries[3].bestk, None);
+    assert_eq!(summary.notaries[0].name, "blackice_DEV");
+
+    // each height is a round of its own, get is the highest one
+    rounds.update(&round_msg(4, 99, 1, 0b0011));
+    assert_eq!(rounds.get("MARTY").unwrap().notaries.len(), 4);
+    assert_eq!(rounds.get_height("MARTY", 99).unwrap().notaries.len(), 1);
+    rounds.update(&round_msg(4, 101, 255, 0));
+    let round = rounds.get("MARTY").unwrap();
+    assert_eq!(round.height, 101);
+    assert_eq!(round.notaries.len(), 1);
+    assert_eq!(round.consensus(), None);
+    assert_eq!(rounds.get_height("MARTY", 100).unwrap().notaries.len(), 4);
+    assert_eq!(rounds.summaries().len(), 1);
+}
+
+#[test]
+fn test_round_timeline() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut rounds = Rounds::new().with_required_sigs(2);
+    let mut update = |msg: DpowNanoMsgHdr, now: u32| {
+        let changes = rounds.update_at(&msg, now);
+        for change in changes.iter() {
+            conn.save_round_change(change);
+        }
+        changes.len()
+    };
+
+    // started and joined, then joined
+    assert_eq!(update(round_msg(0, 100, 255, 0), 1000), 2);
+    assert_eq!(update(round_msg(1, 100, 0, 0b0011), 1005), 1);
+    // a repeat message changes nothing to persist
+    assert_eq!(update(round_msg(1, 100, 0, 0b0011), 1006), 0);
+    // notary 0 now agrees with notary 1 on the bestmask they are both in
+    assert_eq!(update(round_msg(0, 100, 0, 0b0011), 1030), 1);
+    // the next height ends the round
+    assert_eq!(update(round_msg(2, 110, 255, 0), 1100), 3);
+    // which still counts late messages while it is in the window
+    assert_eq!(update(round_msg(3, 100, 0, 0b0011), 1101), 1);
+
+    let history = get_round_history(&conn, "MARTY", 10);
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].height, 110);
+    assert_eq!(history[0].ended, None);
+    let round = &history[1];
+    assert_eq!(
+        (round.started, round.converged, round.ended),
+        (1000, Some(1030), Some(1100))
+    );
+    assert_eq!(round.latency(), Some(30));
+    assert_eq!(round.bestk, Some(0));
+    assert_eq!(round.bestmask, Some("0000000000000003".to_string()));
+    assert_eq!(round.notaries, 2);
+    assert_eq!(
+        get_round_notaries(&conn, "MARTY", 100),
+        vec![(0, 1000), (1, 1005), (3, 1101)]
+    );
+}
+
+#[test]
+fn test_idle_symbols_evicted() {
+    let mut rounds = Rounds::new().with_max_symbols(2);
+    rounds.update_at(&symbol_msg("MARTY", 0, 100), 1000);
+    rounds.update_at(&symbol_msg("DOC", 0, 200), 1010);
+    rounds.update_at(&symbol_msg("MARTY", 0, 101), 1020);
+    // DOC was updated least recently, so it makes way and its open round ends
+    let changes = rounds.update_at(&symbol_msg("KMD", 0, 300), 1030);
+    assert!(rounds.get("DOC").is_none());
+    assert_eq!(rounds.summaries().len(), 2);
+    match &changes[0] {
+        RoundChange::Round(record) => {
+            assert_eq!((record.symbol.as_str(), record.ended), ("DOC", Some(1030)))
+        }
+        change => panic!("unexpected change {:?}", change),
+    }
+}
+
+#[test]
+fn test_fork_detection() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut rounds = Rounds::new();
+    let mut forks = vec![];
+    for (senderind, srchash, now) in [(0, 1, 1000), (1, 1, 1001), (2, 2, 1002), (3, 2, 1003)] {
+        let mut msg = round_msg(senderind, 100, 255, 0);
+        msg.srchash = [srchash; 32];
+        for change in rounds.update_at(&msg, now) {
+            conn.save_round_change(&change);
+            if let RoundChange::Fork { fork, new_hash } = change {
+                forks.push((fork.sides.len(), new_hash));
+            }
+        }
+    }
+    // the disagreement appears with notary 2 and notary 3 joins its side
+    assert_eq!(forks, vec![(2, true), (2, false)]);
+
+    let forks = get_forks(&conn, Some("MARTY"), 10);
+    assert_eq!(forks.len(), 1);
+    let fork = &forks[0];
+    assert_eq!((fork.height, fork.kind.as_str()), (100, "srchash"));
+    assert_eq!((fork.detected, fork.updated), (1002, 1003));
+    assert_eq!(fork.sides.len(), 2);
+    assert_eq!(fork.sides[0].hash, hex::encode([1; 32]));
+    assert_eq!(fork.sides[0].notaries, vec!["blackice_DEV", "blackice_AR"]);
+    assert_eq!(fork.sides[1].notaries, vec!["blackice_EU", "blackice_NA"]);
+    assert!(get_forks(&conn, Some("KMD"), 10).is_empty());
+}
+
+#[test]
+fn test_fork_hash_replaced() {
+    let mut rounds = Rounds::new();
+    let mut forks = vec![];
+    // notary 1 moves from hash 2 to hash 3, so a side empties as a new hash appears
+    for (senderind, srchash) in [(0, 1), (1, 2), (1, 3), (2, 3)] {
+        let mut msg = round_msg(senderind, 100, 255, 0);
+        msg.srchash = [srchash; 32];
+        for change in rounds.update_at(&msg, 1000) {
+            if let RoundChange::Fork { fork, new_hash } = change {
+                forks.push((fork.sides.len(), new_hash));
+            }
+        }
+    }
+    assert_eq!(forks, vec![(2, true), (2, true), (2, false)]);
+}
+
+#[test]
+fn test_fork_on_older_height() {
+    let mut rounds = Rounds::new();
+    let mut forks = vec![];
+    // notary 2 is on a node that is behind and still reports 100, on another chain
+    for (senderind, height, srchash) in [(0, 100, 1), (1, 100, 1), (0, 110, 3), (2, 100, 2)] {
+        let mut msg = round_msg(senderind, height, 255, 0);
+        msg.srchash = [srchash; 32];
+        for change in rounds.update_at(&msg, 1000) {
+            if let RoundChange::Fork { fork, .. } = change {
+                forks.push((fork.height, fork.sides.len()));
+            }
+        }
+    }
+    assert_eq!(forks, vec![(100, 2)]);
+    assert_eq!(rounds.get("MARTY").unwrap().height, 110);
+    let sides = rounds
+        .get_height("MARTY", 100)
+        .unwrap()
+        .sides(HashKind::Src);
+    assert_eq!(sides[1].notaries, vec!["blackice_EU"]);
+}
+
+#[test]
+fn test_notary_participation() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut rounds = Rounds::new().with_required_sigs(2);
+    for (msg, now) in [
+        (round_msg(0, 100, 255, 0), 1000),
+        (round_msg(1, 100, 0, 0b0011), 1005),
+        (round_msg(0, 100, 0, 0b0011), 1030),
+        (round_msg(2, 110, 255, 0), 1100),
+        (round_msg(0, 110, 255, 0), 1110),
+        // late for 100, but that round is still open
+        (round_msg(2, 100, 255, 0), 1120),
+    ] {
+        for change in rounds.update_at(&msg, now).iter() {
+            conn.save_round_change(change);
+        }
+    }
+
+    let stats = notary_participation(&conn, 0, u32::MAX);
+    assert_eq!(stats.len(), 64);
+    let total = &stats[0].total;
+    assert_eq!((total.rounds, total.in_bestmask, total.absent), (2, 1, 0));
+    assert_eq!(total.bestmask_pct, 50.0);
+    assert_eq!(total.avg_first_message_secs, Some(5.0));
+    assert_eq!(&stats[0].symbols["MARTY"], total);
+
+    let total = &stats[1].total;
+    assert_eq!((total.rounds, total.in_bestmask, total.absent), (2, 1, 1));
+    assert_eq!(total.avg_first_message_secs, Some(5.0));
+    let total = &stats[2].total;
+    assert_eq!((total.in_bestmask, total.absent), (0, 0));
+    assert_eq!(total.avg_first_message_secs, Some(60.0));
+    assert_eq!(stats[3].total.avg_first_message_secs, None);
+
+    // only the round started at 1100
+    let stats = notary_participation(&conn, 1050, 2000);
+    assert_eq!(stats[1].total.rounds, 1);
+    assert_eq!(stats[1].total.absent, 1);
+    assert_eq!(stats[0].total.avg_first_message_secs, Some(10.0));
+}
@@ -0,0 +1,61 @@
+use iguana_rs::config::{Config, RpcAuth, RpcConfig};
+use iguana_rs::db::init_db;
+use iguana_rs::rpc::{add_query_methods, request_role, Role, RpcHandler, RpcMeta};
+use jsonrpc_http_server::hyper::header::HeaderValue;
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_rpc_roles() {
+    let config: Config = serde_json::from_str(
+        r#"{"rpc": {
+            "bind": "0.0.0.0:3030",
+            "read_auth": {"basic": {"username": "dashboard", "password": "hunter2"}},
+            "admin_auth": {"bearer": "s3cret"}
+        }}"#,
+    )
+    .unwrap();
+    let rpc = &config.rpc;
+    assert_eq!(rpc.cors, None);
+
+    // base64("dashboard:hunter2")
+    let read = HeaderValue::from_static("Basic ZGFzaGJvYXJkOmh1bnRlcjI=");
+    let admin = HeaderValue::from_static("Bearer s3cret");
+    let wrong = HeaderValue::from_static("Bearer guess");
+    assert_eq!(request_role(rpc, Some(&read)), Some(Role::Read));
+    assert_eq!(request_role(rpc, Some(&admin)), Some(Role::Admin));
+    assert_eq!(request_role(rpc, Some(&wrong)), None);
+    assert_eq!(request_role(rpc, None), None);
+
+    // open reads, protected admin
+    let rpc = RpcConfig {
+        admin_auth: Some(RpcAuth::Bearer("s3cret".to_string())),
+        ..RpcConfig::default()
+    };
+    assert_eq!(request_role(&rpc, None), Some(Role::Read));
+    assert_eq!(request_role(&rpc, Some(&admin)), Some(Role::Admin));
+
+    // the default config keeps the old unauthenticated behaviour
+    assert_eq!(request_role(&RpcConfig::default(), None), Some(Role::Admin));
+}
+
+#[test]
+fn test_rpc_u32_params() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut io = RpcHandler::default();
+    add_query_methods(&mut io, Arc::new(Mutex::new(conn)));
+    let call = |params: &str| {
+        let request = format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "method": "get_spam_incidents", "params": {}}}"#,
+            params
+        );
+        io.handle_request_sync(&request, RpcMeta::default())
+            .unwrap()
+    };
+
+    assert!(call(r#"{"limit": 10}"#).contains(r#""result":[]"#));
+    // 2^32 would have been a limit of 0
+    assert!(call(r#"{"limit": 4294967296}"#).contains("-32602"));
+    assert!(call(r#"{"limit": -1}"#).contains("-32602"));
+}
@@ -0,0 +1,81 @@
+use iguana_rs::db::{get_known_ips, init_db};
+use iguana_rs::metrics::Metrics;
+use iguana_rs::packet::{decode_packet, PacketError};
+use iguana_rs::pipeline::Pipeline;
+use iguana_rs::rounds::Rounds;
+use iguana_rs::simulator::{Fault, Simulator};
+use iguana_rs::subscriptions::Subscriptions;
+use iguana_rs::DPOW_MINSIGS;
+use rusqlite::Connection;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_simulated_round() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let rounds = Arc::new(Mutex::new(Rounds::new()));
+    let pipeline = Pipeline::new(
+        conn,
+        rounds.clone(),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
+    );
+
+    let mut simulator = Simulator::new(16, "MARTY", 1000);
+    // DPOW_MINSIGS steps before anyone has a bestk
+    for _ in 0..DPOW_MINSIGS {
+        for packet in simulator.step() {
+            pipeline.process(&packet, 1_600_000_000);
+        }
+    }
+    assert_eq!(
+        rounds.lock().unwrap().get("MARTY").unwrap().consensus(),
+        None
+    );
+
+    // in the last step of a round every notary has heard from every other
+    while simulator.height == 1000 {
+        for packet in simulator.step() {
+            pipeline.process(&packet, 1_600_000_000);
+        }
+    }
+    {
+        let rounds = rounds.lock().unwrap();
+        let round = rounds.get("MARTY").unwrap();
+        assert_eq!(round.height, 1000);
+        assert_eq!(round.notaries.len(), 16);
+        let (bestk, bestmask, count) = round.consensus().unwrap();
+        // the notaries from 100 % 16 on, the last of them is bestk
+        assert_eq!(bestk, (100 % 16 + DPOW_MINSIGS as u8 - 1) % 16);
+        assert_eq!(bestmask.count_ones() as usize, DPOW_MINSIGS);
+        assert_eq!(count, 16);
+    }
+    assert_eq!(get_known_ips(&pipeline.storage).len(), 16);
+
+    for packet in simulator.step() {
+        pipeline.process(&packet, 1_600_000_060);
+    }
+    let rounds = rounds.lock().unwrap();
+    let round = rounds.get("MARTY").unwrap();
+    assert_eq!(round.height, 1010);
+    assert_eq!(round.consensus(), None);
+}
+
+#[test]
+fn test_injected_faults() {
+    let simulator = Simulator::new(4, "MARTY", 1000);
+    let decode =
+        |fault| decode_packet(&simulator.faulty_packet(2, fault)).map(|(packet, _)| packet);
+
+    assert_eq!(
+        decode(Fault::BadSignature).unwrap_err(),
+        PacketError::BadSignature
+    );
+    assert_eq!(
+        decode(Fault::Truncated).unwrap_err(),
+        PacketError::Truncated
+    );
+    // nothing ties a pubkey to a senderind yet, so this one still decodes
+    let packet = decode(Fault::WrongSenderind).unwrap();
+    assert_eq!(packet.dpow_msg.senderind, 3);
+}
@@ -0,0 +1,172 @@
+use iguana_rs::sp::{sp_header, SpBus, SP_BUS_PROTOCOL};
+use iguana_rs::transport::Transport;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn wait_for_pipes(bus: &SpBus, pipes: usize) {
+    let start = Instant::now();
+    while bus.connected_pipes() < pipes {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "peers never connected"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn bound_bus() -> (SpBus, String) {
+    let mut bus = SpBus::new();
+    bus.set_receive_timeout(Some(Duration::from_secs(5)));
+    let addr = bus.bind("tcp://127.0.0.1:0").unwrap();
+    (bus, addr)
+}
+
+#[test]
+fn test_sp_bus() {
+    let (mut server, addr) = bound_bus();
+    let mut client = SpBus::new();
+    client.set_receive_timeout(Some(Duration::from_secs(5)));
+    client.connect(&addr).unwrap();
+    wait_for_pipes(&server, 1);
+    wait_for_pipes(&client, 1);
+
+    client.send(b"hello").unwrap();
+    assert_eq!(server.recv().unwrap(), Some(b"hello".to_vec()));
+    server.send(b"").unwrap();
+    assert_eq!(client.recv().unwrap(), Some(vec![]));
+
+    client.disconnect(&addr).unwrap();
+    assert!(client.disconnect(&addr).is_err());
+    let start = Instant::now();
+    while server.connected_pipes() > 0 {
+        assert!(start.elapsed() < Duration::from_secs(5));
+        thread::sleep(Duration::from_millis(10));
+    }
+    server.set_receive_timeout(Some(Duration::from_millis(10)));
+    assert_eq!(server.recv().unwrap(), None);
+    assert!(client.connect("ipc:///tmp/iguana.ipc").is_err());
+}
+
+#[test]
+fn test_sp_bus_drop() {
+    let (server, addr) = bound_bus();
+    let mut client = SpBus::new();
+    client.connect(&addr).unwrap();
+    wait_for_pipes(&server, 1);
+    let start = Instant::now();
+    drop(server);
+    drop(client);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    // the accept thread has closed the listening socket
+    TcpListener::bind(addr.strip_prefix("tcp://").unwrap()).unwrap();
+}
+
+#[test]
+fn test_sp_wire_format() {
+    let (mut server, addr) = bound_bus();
+    server.set_receive_max_size(16);
+    let tcp_addr = addr.strip_prefix("tcp://").unwrap();
+
+    let mut stream = TcpStream::connect(tcp_addr).unwrap();
+    stream.write_all(&[0, b'S', b'P', 0, 0, 112, 0, 0]).unwrap();
+    let mut header = [0u8; 8];
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(header, sp_header(SP_BUS_PROTOCOL));
+    stream
+        .write_all(&[0, 0, 0, 0, 0, 0, 0, 3, 1, 2, 3])
+        .unwrap();
+    assert_eq!(server.recv().unwrap(), Some(vec![1, 2, 3]));
+
+    // the server closes the connection on messages over the max size
+    stream.write_all(&17u64.to_be_bytes()).unwrap();
+    assert_eq!(stream.read(&mut header).unwrap_or(0), 0);
+
+    // and on sockets that are not a bus, eg a pair socket
+    let mut stream = TcpStream::connect(tcp_addr).unwrap();
+    stream.write_all(&sp_header(16)).unwrap();
+    stream.read_exact(&mut header).unwrap();
+    assert_eq!(stream.read(&mut header).unwrap_or(0), 0);
+}
+
+#[test]
+fn test_sp_bus_limits() {
+    let (mut server, addr) = bound_bus();
+    server.set_max_accepted(1);
+    let tcp_addr = addr.strip_prefix("tcp://").unwrap();
+    let mut header = [0u8; 8];
+
+    let mut first = TcpStream::connect(tcp_addr).unwrap();
+    first.write_all(&sp_header(SP_BUS_PROTOCOL)).unwrap();
+    first.read_exact(&mut header).unwrap();
+    wait_for_pipes(&server, 1);
+    // one over the limit is closed without a handshake
+    let mut second = TcpStream::connect(tcp_addr).unwrap();
+    assert_eq!(second.read(&mut header).unwrap_or(0), 0);
+
+    // a peer that never reads doesn't hold up send
+    let start = Instant::now();
+    for _ in 0..2000 {
+        server.send(&[0; 4096]).unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_secs(1));
+    drop(first);
+
+    // disconnecting from a peer stuck in the handshake returns straight away
+    let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+    let silent_addr = format!("tcp://{}", silent.local_addr().unwrap());
+    let mut client = SpBus::new();
+    client.connect(&silent_addr).unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let start = Instant::now();
+    client.disconnect(&silent_addr).unwrap();
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
+
+// interop with the libnanomsg the nanomsg transport links against
+#[test]
+fn test_sp_bus_against_libnanomsg() {
+    use nanomsg::{Protocol, Socket};
+
+    let (mut server, addr) = bound_bus();
+    let mut socket = Socket::new(Protocol::Bus).unwrap();
+    socket.set_receive_timeout(5000).unwrap();
+    let _endpoint = socket.connect(&addr).unwrap();
+    wait_for_pipes(&server, 1);
+
+    socket.write_all(b"from libnanomsg").unwrap();
+    assert_eq!(server.recv().unwrap(), Some(b"from libnanomsg".to_vec()));
+    server.send(b"from SpBus").unwrap();
+    let mut buffer = vec![];
+    socket.read_to_end(&mut buffer).unwrap();
+    assert_eq!(buffer, b"from SpBus");
+}
+
+// the listener dials iguana, so SpBus connecting to a libnanomsg bind is the usual case
+#[test]
+fn test_sp_bus_dialling_libnanomsg() {
+    use nanomsg::{Protocol, Socket};
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let addr = format!("tcp://127.0.0.1:{}", port);
+    let mut socket = Socket::new(Protocol::Bus).unwrap();
+    socket.set_receive_timeout(5000).unwrap();
+    let _endpoint = socket.bind(&addr).unwrap();
+
+    let mut client = SpBus::new();
+    client.set_receive_timeout(Some(Duration::from_secs(5)));
+    client.connect(&addr).unwrap();
+    wait_for_pipes(&client, 1);
+
+    client.send(b"from SpBus").unwrap();
+    let mut buffer = vec![];
+    socket.read_to_end(&mut buffer).unwrap();
+    assert_eq!(buffer, b"from SpBus");
+    socket.write_all(b"from libnanomsg").unwrap();
+    assert_eq!(client.recv().unwrap(), Some(b"from libnanomsg".to_vec()));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::symbol_msg;
+use futures::executor::block_on;
+use futures::StreamExt;
+use iguana_rs::message::DecodedMessage;
+use iguana_rs::subscriptions::{messages_pubsub_handler, Subscriptions};
+use jsonrpc_pubsub::Session;
+use std::sync::Arc;
+
+#[test]
+fn test_decoded_message() {
+    let mut msg = symbol_msg("MARTY", 8, 1000);
+    msg.myipbits = [1, 2, 3, 4];
+    msg.notarize.bestk = 255;
+    msg.notarize.recvmask = 0b1_0000_0001u64.to_le_bytes();
+
+    let decoded = DecodedMessage::new(&msg);
+    assert_eq!(decoded.sender, "alright_EU");
+    assert_eq!(decoded.myip, "1.2.3.4");
+    assert_eq!(decoded.symbol, "MARTY");
+    assert_eq!(decoded.bestk, None);
+    assert_eq!(decoded.recvmask, "0000000000000101");
+    assert_eq!(
+        decoded.recvmask_notaries,
+        vec!["blackice_DEV", "alright_EU"]
+    );
+    assert!(decoded.bestmask_notaries.is_empty());
+}
+
+#[test]
+fn test_filtered_subscription() {
+    let subscriptions = Subscriptions::new();
+    let io = messages_pubsub_handler(subscriptions.clone());
+    let (sender, mut receiver) = futures::channel::mpsc::unbounded();
+    let session = Arc::new(Session::new(sender));
+
+    let response = io
+        .handle_request_sync(
+            r#"{"jsonrpc":"2.0","id":1,"method":"subscribe_messages","params":{"symbol":"MARTY"}}"#,
+            session.clone(),
+        )
+        .unwrap();
+    assert!(response.contains(r#""result":0"#));
+    assert!(!subscriptions.is_empty());
+
+    subscriptions.publish(&DecodedMessage::new(&symbol_msg("KMD", 1, 10)));
+    subscriptions.publish(&DecodedMessage::new(&symbol_msg("MARTY", 2, 20)));
+
+    // the KMD message was filtered out, so the first notification is MARTY
+    let notification = block_on(receiver.next()).unwrap();
+    assert!(notification.contains(r#""method":"message""#));
+    assert!(notification.contains(r#""symbol":"MARTY""#));
+    assert!(notification.contains(r#""height":20"#));
+
+    // another connection can't cancel it
+    let unsubscribe = r#"{"jsonrpc":"2.0","id":2,"method":"unsubscribe_messages","params":[0]}"#;
+    let (other_sender, _other_receiver) = futures::channel::mpsc::unbounded();
+    let other = Arc::new(Session::new(other_sender));
+    let response = io.handle_request_sync(unsubscribe, other).unwrap();
+    assert!(response.contains("-32602"));
+
+    let response = io.handle_request_sync(unsubscribe, session).unwrap();
+    assert!(response.contains(r#""result":true"#));
+    assert!(subscriptions.is_empty());
+}
+
+#[test]
+fn test_subscriptions_dropped_with_session() {
+    let subscriptions = Subscriptions::new();
+    let io = messages_pubsub_handler(subscriptions.clone());
+    let (sender, _receiver) = futures::channel::mpsc::unbounded();
+    let session = Arc::new(Session::new(sender));
+    io.handle_request_sync(
+        r#"{"jsonrpc":"2.0","id":1,"method":"subscribe_messages","params":{"symbol":"QUIET"}}"#,
+        session.clone(),
+    )
+    .unwrap();
+    assert!(!subscriptions.is_empty());
+
+    // nothing is ever published for QUIET, closing the connection is what removes it
+    drop(session);
+    assert!(subscriptions.is_empty());
+}
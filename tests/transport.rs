@@ -0,0 +1,120 @@
+use iguana_rs::capture::{CaptureReader, CAPTURE_MAGIC};
+use iguana_rs::db::init_db;
+use iguana_rs::metrics::Metrics;
+use iguana_rs::peers::{PeerCommand, PeerRequest, Peers};
+use iguana_rs::pipeline::Pipeline;
+use iguana_rs::rounds::Rounds;
+use iguana_rs::simulator::Simulator;
+use iguana_rs::subscriptions::Subscriptions;
+use iguana_rs::transport::{MemoryTransport, ReplayTransport, Transport};
+use rusqlite::Connection;
+use std::io::ErrorKind;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+fn pipeline() -> Pipeline {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    Pipeline::new(
+        conn,
+        Arc::new(Mutex::new(Rounds::new())),
+        Subscriptions::new(),
+        Arc::new(Metrics::new()),
+    )
+}
+
+#[test]
+fn test_memory_transport() {
+    let pipeline = pipeline();
+    let mut peers = Peers::new(&pipeline.storage, "7775");
+    let (mut transport, handle) = MemoryTransport::new(Duration::from_millis(10));
+
+    let mut simulator = Simulator::new(4, "MARTY", 1000);
+    for packet in simulator.step() {
+        handle.incoming.send(packet).unwrap();
+    }
+    let (peer_commands, peer_requests) = channel();
+    let (reply, replies) = channel();
+    peer_commands
+        .send(PeerRequest {
+            command: PeerCommand::Ban("127.0.0.3".to_string()),
+            reply,
+        })
+        .unwrap();
+    // the pipeline runs until the handle is dropped and the queued messages are used up
+    drop(handle.incoming);
+
+    pipeline
+        .run(&mut transport, &mut peers, &peer_requests, None, None)
+        .unwrap();
+    assert_eq!(replies.recv().unwrap().unwrap().banned, vec!["127.0.0.3"]);
+    // every advertised IP is dialled except the banned one
+    assert_eq!(
+        transport.connected(),
+        vec![
+            "tcp://127.0.0.1:7775",
+            "tcp://127.0.0.2:7775",
+            "tcp://127.0.0.4:7775"
+        ]
+    );
+    let rounds = pipeline.rounds.lock().unwrap();
+    assert_eq!(rounds.get("MARTY").unwrap().notaries.len(), 4);
+
+    transport.send(b"out").unwrap();
+    assert_eq!(handle.outgoing.recv().unwrap(), b"out");
+}
+
+#[test]
+fn test_replay_transport() {
+    let mut simulator = Simulator::new(2, "MARTY", 1000);
+    let mut capture = CAPTURE_MAGIC.to_vec();
+    for (i, packet) in simulator.step().into_iter().enumerate() {
+        capture.extend((1_600_000_000_000_000 + i as u64 * 1_000_000).to_le_bytes());
+        capture.extend((packet.len() as u32).to_le_bytes());
+        capture.extend(packet);
+    }
+
+    let reader = CaptureReader::new(&capture[..]).unwrap();
+    let mut transport = ReplayTransport::new(reader, false);
+    assert!(transport.recv().unwrap().is_some());
+    assert_eq!(transport.last_received(), Some(1_600_000_000_000_000));
+
+    let pipeline = pipeline();
+    let mut peers = Peers::new(&pipeline.storage, "7775");
+    let (_peer_commands, peer_requests) = channel();
+    pipeline
+        .run(&mut transport, &mut peers, &peer_requests, None, None)
+        .unwrap();
+    // only the second message was left, stored with its capture time
+    let notaries = iguana_rs::db::get_notaries(&pipeline.storage);
+    assert_eq!(notaries[0].lastseen, 0);
+    assert_eq!(notaries[1].lastseen, 1_600_000_001);
+}
+
+#[test]
+fn test_replay_truncated_capture() {
+    let mut simulator = Simulator::new(2, "MARTY", 1000);
+    let mut capture = CAPTURE_MAGIC.to_vec();
+    for packet in simulator.step() {
+        capture.extend(1_600_000_000_000_000u64.to_le_bytes());
+        capture.extend((packet.len() as u32).to_le_bytes());
+        capture.extend(packet);
+    }
+    capture.truncate(capture.len() - 10);
+
+    let reader = CaptureReader::new(&capture[..]).unwrap();
+    let mut transport = ReplayTransport::new(reader, false);
+    let pipeline = pipeline();
+    let mut peers = Peers::new(&pipeline.storage, "7775");
+    let (_peer_commands, peer_requests) = channel();
+    let err = pipeline
+        .run(&mut transport, &mut peers, &peer_requests, None, None)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    // the records before the truncated one were still processed
+    assert_eq!(
+        iguana_rs::db::get_notaries(&pipeline.storage)[0].lastseen,
+        1_600_000_000
+    );
+}
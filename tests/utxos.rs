@@ -0,0 +1,69 @@
+mod common;
+
+use common::symbol_msg;
+use iguana_rs::db::{get_notary_utxos, init_db};
+use iguana_rs::storage::Storage;
+use iguana_rs::utxos::UtxoTracker;
+use iguana_rs::DpowNanoMsgHdr;
+use rusqlite::Connection;
+
+fn utxo_msg(senderind: u8, height: u32, srcutxo: u8, destutxo: u8) -> DpowNanoMsgHdr {
+    let mut msg = symbol_msg("MARTY", senderind, height);
+    msg.notarize.srcutxo = [srcutxo; 32];
+    msg.notarize.srcvout = 1;
+    msg.notarize.destutxo = [destutxo; 32];
+    msg
+}
+
+#[test]
+fn test_utxo_tracking() {
+    let conn = Connection::open_in_memory().unwrap();
+    init_db(&conn);
+    let mut tracker = UtxoTracker::default();
+    let mut update = |msg: DpowNanoMsgHdr, now: u32| {
+        let utxos = tracker.update_at(&msg, now);
+        for utxo in utxos.iter() {
+            conn.save_utxo(utxo);
+        }
+        utxos
+    };
+
+    let utxos = update(utxo_msg(0, 100, 1, 2), 1000);
+    assert_eq!(utxos.len(), 2);
+    assert_eq!(utxos[0].kind, "src");
+    assert_eq!(utxos[0].txid, Some(hex::encode([1; 32])));
+    assert_eq!(utxos[0].vout, Some(1));
+    assert_eq!(utxos[0].previous, None);
+    // repeat messages change nothing
+    assert!(update(utxo_msg(0, 100, 1, 2), 1001).is_empty());
+
+    // no dest UTXO
+    let utxos = update(utxo_msg(1, 100, 3, 0), 1002);
+    assert_eq!(utxos.len(), 2);
+    assert_eq!((utxos[1].txid.clone(), utxos[1].vout), (None, None));
+
+    // the same src UTXO in the next round is fine until it is spent, then another notary
+    // offers the same dest UTXO
+    let utxos = update(utxo_msg(0, 110, 1, 4), 1100);
+    assert_eq!(utxos[0].previous, None);
+    assert_eq!(utxos[1].previous, None);
+    let utxos = update(utxo_msg(2, 110, 5, 4), 1101);
+    assert_eq!(utxos[1].previous.as_ref().unwrap().senderind, 0);
+
+    assert_eq!(get_notary_utxos(&conn, None, false, 100).len(), 8);
+    assert_eq!(get_notary_utxos(&conn, Some("KMD"), false, 100).len(), 0);
+    let problems = get_notary_utxos(&conn, Some("MARTY"), true, 100);
+    let problems: Vec<(u8, u32, &str)> = problems
+        .iter()
+        .map(|utxo| (utxo.senderind, utxo.height, utxo.kind.as_str()))
+        .collect();
+    assert_eq!(problems, vec![(2, 110, "dest"), (1, 100, "dest")]);
+
+    // offers expire after max_age, in the order they were made
+    let mut tracker = UtxoTracker::new(100);
+    tracker.update_at(&utxo_msg(0, 100, 1, 2), 1000);
+    tracker.update_at(&utxo_msg(1, 100, 3, 4), 1050);
+    let utxos = tracker.update_at(&utxo_msg(2, 110, 1, 4), 1120);
+    assert_eq!(utxos[0].previous, None);
+    assert_eq!(utxos[1].previous.as_ref().unwrap().senderind, 1);
+}